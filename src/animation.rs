@@ -0,0 +1,169 @@
+use std::time::{Duration, Instant};
+use crate::config::Config;
+use crate::constants::{RAMP_DURATION_MS, RAMP_FRAME_MS};
+use crate::logging::log_e;
+
+// ramp easing, applied to the perceptual (curve input) position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Easing { Linear, EaseOut, Exponential }
+impl Easing {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "linear" => Some(Easing::Linear),
+            "ease-out" | "easeout" => Some(Easing::EaseOut),
+            "exp" | "exponential" => Some(Easing::Exponential),
+            _ => None,
+        }
+    }
+
+    // t in 0..1 -> progress in 0..1, exact at both ends
+    pub(crate) fn apply(self, t: f32) -> f32 {
+        if t <= 0.0 { return 0.0; }
+        if t >= 1.0 { return 1.0; }
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t) * (1.0 - t),
+            Easing::Exponential => (1.0 - 2f32.powf(-10.0 * t)) / (1.0 - 2f32.powf(-10.0)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RampConfig { pub(crate) duration: Duration, pub(crate) easing: Easing, pub(crate) frame: Duration }
+impl RampConfig {
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        let easing = match cfg.ramp_easing.as_deref() {
+            None => Easing::EaseOut,
+            Some(s) => Easing::parse(s).unwrap_or_else(|| {
+                log_e(&format!("[Ramp] Unknown easing '{}', using ease-out", s));
+                Easing::EaseOut
+            }),
+        };
+        Self {
+            duration: Duration::from_millis(cfg.ramp_duration_ms.unwrap_or(RAMP_DURATION_MS)),
+            easing,
+            frame: Duration::from_millis(cfg.ramp_frame_ms.unwrap_or(RAMP_FRAME_MS).max(1)),
+        }
+    }
+}
+
+// time based brightness ramp between perceptual positions (0..1 of the input range)
+// the active curve maps positions to hw levels, so low-brightness steps stay even
+pub(crate) struct Ramp { cfg: RampConfig, from: f32, to: f32, start: Instant, active: bool }
+impl Ramp {
+    pub(crate) fn new(cfg: RampConfig, pos: f32, now: Instant) -> Self {
+        Self { cfg, from: pos, to: pos, start: now, active: false }
+    }
+
+    pub(crate) fn frame(&self) -> Duration { self.cfg.frame }
+    pub(crate) fn is_active(&self) -> bool { self.active }
+    pub(crate) fn target(&self) -> f32 { self.to }
+
+    // instant move, used for screen on/off
+    pub(crate) fn jump(&mut self, pos: f32) {
+        self.from = pos;
+        self.to = pos;
+        self.active = false;
+    }
+
+    // stop where we are, the caller writes something else
+    pub(crate) fn cancel(&mut self, now: Instant) {
+        let pos = self.position(now);
+        self.jump(pos);
+    }
+
+    // start (or redirect) a ramp from the current position
+    pub(crate) fn retarget(&mut self, to: f32, now: Instant) {
+        if (to - self.to).abs() < f32::EPSILON { return; }
+        if self.cfg.duration.is_zero() { self.jump(to); return; }
+        self.from = self.position(now);
+        self.to = to;
+        self.start = now;
+        self.active = true;
+    }
+
+    // current position, finishes the ramp once the duration has passed
+    pub(crate) fn sample(&mut self, now: Instant) -> f32 {
+        let pos = self.position(now);
+        if self.active && now.saturating_duration_since(self.start) >= self.cfg.duration {
+            self.active = false;
+        }
+        pos
+    }
+
+    fn position(&self, now: Instant) -> f32 {
+        if !self.active { return self.to; }
+        let t = now.saturating_duration_since(self.start).as_secs_f32() / self.cfg.duration.as_secs_f32();
+        self.from + (self.to - self.from) * self.cfg.easing.apply(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::{persist_ramp_duration_prop, persist_ramp_easing_prop, persist_ramp_frame_prop};
+    use crate::properties::MemoryProps;
+
+    fn ramp_cfg(duration_ms: u64, easing: Easing) -> RampConfig {
+        RampConfig { duration: Duration::from_millis(duration_ms), easing, frame: Duration::from_millis(20) }
+    }
+
+    #[test]
+    fn easings_hit_both_ends_and_rise() {
+        for easing in [Easing::Linear, Easing::EaseOut, Easing::Exponential] {
+            assert_eq!((easing.apply(0.0), easing.apply(1.0)), (0.0, 1.0), "{:?}", easing);
+            assert_eq!((easing.apply(-1.0), easing.apply(2.0)), (0.0, 1.0), "{:?}", easing);
+            for i in 1..=100 {
+                assert!(easing.apply(i as f32 / 100.0) > easing.apply((i - 1) as f32 / 100.0), "{:?} at {}", easing, i);
+            }
+        }
+        assert_eq!(Easing::parse("easeout"), Some(Easing::EaseOut));
+        assert_eq!(Easing::parse("bounce"), None);
+    }
+
+    #[test]
+    fn ramps_in_frames_and_retargets_without_a_jump() {
+        let t0 = Instant::now();
+        let ms = |v: u64| t0 + Duration::from_millis(v);
+        let mut ramp = Ramp::new(ramp_cfg(100, Easing::Linear), 0.0, t0);
+        ramp.retarget(1.0, t0);
+        // sampled every frame: 5 steps, done at the duration
+        for i in 1..=5 {
+            assert!((ramp.sample(ms(i * 20)) - i as f32 * 0.2).abs() < 1e-6, "frame {}", i);
+        }
+        assert!(!ramp.is_active());
+
+        // redirected halfway it carries on from where it is
+        ramp.retarget(0.0, ms(200));
+        let before = ramp.sample(ms(250));
+        ramp.retarget(0.8, ms(250));
+        assert!((ramp.sample(ms(250)) - before).abs() < 1e-6);
+        assert!(ramp.is_active());
+        assert_eq!((ramp.sample(ms(350)), ramp.target()), (0.8, 0.8));
+
+        // cancel stops in place
+        ramp.retarget(0.0, ms(400));
+        ramp.cancel(ms(450));
+        assert!(!ramp.is_active());
+        assert!((ramp.sample(ms(1000)) - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn zero_duration_jumps_straight_to_the_target() {
+        let t0 = Instant::now();
+        let mut ramp = Ramp::new(ramp_cfg(0, Easing::EaseOut), 0.2, t0);
+        ramp.retarget(0.9, t0);
+        assert!(!ramp.is_active());
+        assert_eq!(ramp.sample(t0), 0.9);
+    }
+
+    #[test]
+    fn config_defaults_and_limits() {
+        let cfg = RampConfig::from_config(&Config::load_files(&MemoryProps::new(), &[]));
+        assert_eq!((cfg.duration, cfg.easing, cfg.frame), (Duration::from_millis(RAMP_DURATION_MS), Easing::EaseOut, Duration::from_millis(RAMP_FRAME_MS)));
+        let props = MemoryProps::with(&[(persist_ramp_duration_prop(), "0"), (persist_ramp_easing_prop(), "bounce"), (persist_ramp_frame_prop(), "0")]);
+        let cfg = RampConfig::from_config(&Config::load_files(&props, &[]));
+        // a 0ms frame would spin
+        assert_eq!((cfg.duration, cfg.easing, cfg.frame), (Duration::ZERO, Easing::EaseOut, Duration::from_millis(1)));
+    }
+}
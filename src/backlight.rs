@@ -0,0 +1,163 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::logging::{log_d, log_e};
+use crate::config::Config;
+use crate::paths::default_backlight_dir;
+
+// name tokens that never belong to a panel backlight
+const REJECT_NAMES: [&str; 12] = [
+    "button", "keyboard", "kpd", "flash", "torch", "vibrator",
+    "red", "green", "blue", "white", "charging", "indicator",
+];
+
+// panel backlight device
+#[derive(Clone, Debug)]
+pub(crate) struct Backlight {
+    pub(crate) name: String,
+    pub(crate) dir: PathBuf,
+}
+impl Backlight {
+    pub(crate) fn from_dir(dir: PathBuf) -> Self {
+        let name = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        Self { name, dir }
+    }
+
+    pub(crate) fn brightness_path(&self) -> PathBuf { self.dir.join("brightness") }
+    pub(crate) fn min_path(&self) -> PathBuf { self.dir.join("min_brightness") }
+
+    // prefer the real hw ceiling over the (often 255) led class max
+    pub(crate) fn max_path(&self) -> PathBuf {
+        let hw = self.dir.join("max_hw_brightness");
+        if hw.exists() { hw } else { self.dir.join("max_brightness") }
+    }
+}
+
+// score a candidate directory, None if it can't be a panel backlight
+fn score_candidate(dir: &Path) -> Option<i32> {
+    if !dir.join("brightness").is_file() { return None; }
+    let name = dir.file_name()?.to_string_lossy().to_lowercase();
+    // whole tokens only, led names look like "red", "white:flash" or "button-backlight"
+    if name.split(['-', '_', ':']).any(|t| REJECT_NAMES.contains(&t)) { return None; }
+
+    let mut score = 0;
+    if name == "lcd-backlight" { score += 50; }
+    if name.contains("panel") || name.contains("lcd") || name.contains("dsi") { score += 20; }
+    if name.contains("backlight") || name.ends_with("-bl") || name.ends_with("_bl") { score += 15; }
+    if dir.join("max_hw_brightness").is_file() { score += 15; }
+    if dir.join("max_brightness").is_file() { score += 10; }
+    if dir.join("min_brightness").is_file() { score += 5; }
+
+    // backlight class type, kernel docs rank firmware > platform > raw
+    if let Ok(kind) = fs::read_to_string(dir.join("type")) {
+        score += match kind.trim() {
+            "firmware" => 10,
+            "platform" => 8,
+            "raw" => 6,
+            _ => 0,
+        };
+    }
+    Some(score)
+}
+
+fn class_dirs(root: &Path) -> Vec<PathBuf> {
+    ["class/leds", "class/backlight"]
+        .iter()
+        .filter_map(|class| fs::read_dir(root.join(class)).ok())
+        .flat_map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()))
+        .collect()
+}
+
+// scan <root>/class/{leds,backlight} and pick the best scoring panel backlight
+// override is either a device name or an absolute device directory
+pub(crate) fn discover(root: &Path, override_dev: Option<&str>, dbg: bool) -> Option<Backlight> {
+    let candidates = class_dirs(root);
+
+    if let Some(dev) = override_dev {
+        let forced = if dev.starts_with('/') {
+            Some(PathBuf::from(dev))
+        } else {
+            candidates.iter().find(|d| d.file_name().is_some_and(|n| n == dev)).cloned()
+        };
+        match forced {
+            Some(dir) if dir.join("brightness").exists() => return Some(Backlight::from_dir(dir)),
+            _ => log_e(&format!("[Backlight] Override '{}' not found, falling back to discovery", dev)),
+        }
+    }
+
+    let mut best: Option<(i32, PathBuf)> = None;
+    for dir in candidates {
+        let Some(score) = score_candidate(&dir) else { continue; };
+        if dbg { log_d(&format!("[Backlight] Candidate {} score={}", dir.display(), score)); }
+        if best.as_ref().is_none_or(|(s, _)| score > *s) {
+            best = Some((score, dir));
+        }
+    }
+    best.map(|(_, dir)| Backlight::from_dir(dir))
+}
+
+// resolve the panel backlight once at startup
+pub(crate) fn resolve_backlight(root: &Path, cfg: &Config, dbg: bool) -> Backlight {
+    let bl = discover(root, cfg.backlight_device.as_deref(), dbg)
+        .unwrap_or_else(|| {
+            log_e("[Backlight] No backlight device found, using default path");
+            Backlight::from_dir(PathBuf::from(default_backlight_dir()))
+        });
+    log_d(&format!("[Backlight] Using {} ({})", bl.name, bl.dir.display()));
+    bl
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+
+    // <root>/class/<class>/<name> with the given files
+    fn device(root: &Path, class: &str, name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = root.join("class").join(class).join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("brightness"), "0").unwrap();
+        for (file, val) in files { fs::write(dir.join(file), val).unwrap(); }
+        dir
+    }
+
+    #[test]
+    fn scores_and_rejects_candidates() {
+        let tmp = TempDir::new();
+        let root = tmp.path();
+        let lcd = device(root, "leds", "lcd-backlight", &[("max_brightness", "2047")]);
+        let panel = device(root, "backlight", "panel0-backlight", &[("max_brightness", "2047"), ("type", "firmware")]);
+        let wled = device(root, "backlight", "wled", &[("max_brightness", "4095"), ("type", "raw")]);
+        assert_eq!(score_candidate(&lcd), Some(95));
+        assert_eq!(score_candidate(&panel), Some(55));
+        assert_eq!(score_candidate(&wled), Some(16));
+        for led in ["red", "white:flash", "button-backlight", "kpd_bl", "charging"] {
+            assert_eq!(score_candidate(&device(root, "leds", led, &[("max_brightness", "255")])), None, "{}", led);
+        }
+        // colors only count as whole tokens
+        assert!(score_candidate(&device(root, "backlight", "credence-panel", &[])).is_some());
+        // no brightness node, no candidate
+        fs::create_dir_all(root.join("class/leds/mmc0")).unwrap();
+        assert_eq!(score_candidate(&root.join("class/leds/mmc0")), None);
+    }
+
+    #[test]
+    fn discovers_the_best_panel_unless_overridden() {
+        let tmp = TempDir::new();
+        let root = tmp.path();
+        assert!(discover(root, None, false).is_none());
+        device(root, "leds", "red", &[("max_brightness", "255")]);
+        device(root, "backlight", "wled", &[("max_brightness", "4095")]);
+        let panel = device(root, "backlight", "panel0-backlight", &[("max_hw_brightness", "4095"), ("max_brightness", "255")]);
+        let found = discover(root, None, false).unwrap();
+        assert_eq!((found.name.as_str(), found.max_path()), ("panel0-backlight", panel.join("max_hw_brightness")));
+        device(root, "leds", "lcd-backlight", &[]);
+        assert_eq!(discover(root, None, false).unwrap().name, "lcd-backlight");
+
+        // backlight.device by name or path, even one discovery would skip, unknown ones fall back
+        assert_eq!(discover(root, Some("wled"), false).unwrap().name, "wled");
+        assert_eq!(discover(root, Some("red"), false).unwrap().name, "red");
+        let elsewhere = device(root, "other", "dsi0", &[]);
+        assert_eq!(discover(root, Some(elsewhere.to_str().unwrap()), false).unwrap().dir, elsewhere);
+        assert_eq!(discover(root, Some("panel9"), false).unwrap().name, "lcd-backlight");
+    }
+}
@@ -0,0 +1,308 @@
+// one-shot diagnostics for `adb shell`, each returns the process exit code
+use std::fs::OpenOptions;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use crate::backlight::{Backlight, discover, resolve_backlight};
+use crate::config::Config;
+use crate::constants::{OS14_MAX, OS14_MIN};
+use crate::curve::{CurveRegistry, ModeWatch, ScalingMode, input_to_pos, pos_to_hw, scale};
+use crate::env::system_settings;
+use crate::outputs::{mirror_entries, parse_mirror};
+use crate::paths::{oplus_bright_path, settings_dir, sysfs_root};
+use crate::properties::PropertyStore;
+use crate::range::BrightnessRange;
+use crate::scaling::load_custom_curve;
+use crate::settings::{Namespace, PANORAMIC_AOD, SettingsReader};
+use crate::settings_xml::{file_name, read_namespace};
+use crate::state::StateSources;
+use crate::input::InputSource;
+use crate::utils::{read_file_int, read_max_brightness, read_min_brightness};
+use crate::writer::{Readback, WriteFormat, write_brightness};
+
+// rows printed by dump-curve
+const DUMP_STEPS: i32 = 20;
+
+// everything the daemon resolves at startup, without entering a loop or updating the persisted caches
+struct Device {
+    cfg: Config,
+    bl: Backlight,
+    hw_min: i32,
+    hw_max: i32,
+    curves: CurveRegistry,
+    mode: ScalingMode,
+    input_min: i32,
+    input_max: i32,
+}
+
+impl Device {
+    fn load(props: &dyn PropertyStore) -> Self { Self::open(props, Config::load(props), Path::new(sysfs_root())) }
+
+    // the same from a given config and sysfs tree
+    fn open(props: &dyn PropertyStore, cfg: Config, sysfs: &Path) -> Self {
+        let dbg = cfg.debug;
+        let bl = resolve_backlight(sysfs, &cfg, dbg);
+        let hw_min = read_min_brightness(props, &cfg, &bl, dbg);
+        let hw_max = read_max_brightness(props, &cfg, &bl, dbg);
+        let curves = CurveRegistry::load(&cfg, hw_min, hw_max);
+        let mode = cfg.live_mode(props, &mut ModeWatch::new(cfg.mode));
+        let mut range = BrightnessRange::init(&cfg);
+        range.read_range(props);
+        let (input_min, input_max) = if cfg.displaypanel {
+            (cfg.panel_min.unwrap_or(OS14_MIN), cfg.panel_max.unwrap_or(OS14_MAX))
+        } else {
+            (range.min, range.max)
+        };
+        Self { cfg, bl, hw_min, hw_max, curves, mode, input_min, input_max }
+    }
+
+    // current framework level from the configured or probed source, with the source's description
+    fn current_input(&self, props: &dyn PropertyStore) -> (String, Option<i32>) {
+        let mut input = InputSource::open(&self.cfg, props, system_settings(), Path::new(oplus_bright_path()), Instant::now());
+        let level = input.read(props, self.input_min, self.input_max, Instant::now());
+        (input.describe(), level)
+    }
+
+    // an input level, or a fraction of the input range when it has a '.'
+    fn parse_input(&self, s: &str) -> Option<i32> {
+        if s.contains('.') {
+            let f = s.parse::<f32>().ok()?.clamp(0.0, 1.0);
+            Some((self.input_min as f32 + f * (self.input_max - self.input_min) as f32).round() as i32)
+        } else {
+            s.parse().ok()
+        }
+    }
+}
+
+fn mode_label(mode: ScalingMode) -> String { format!("{} ({:?})", mode.id(), mode) }
+
+// resolved config, detected hw range, current input and output
+pub fn status(props: &dyn PropertyStore) -> i32 {
+    let dev = Device::load(props);
+    println!("mode:        {}", if dev.cfg.displaypanel { "DisplayPanel" } else { "Default" });
+    println!("backlight:   {} ({})", dev.bl.name, dev.bl.dir.display());
+    println!("hw range:    {}-{}", dev.hw_min, dev.hw_max);
+    println!("input range: {}-{}", dev.input_min, dev.input_max);
+    let curve = dev.curves.get(dev.mode);
+    println!("curve:       {} -> {}: {}", mode_label(dev.mode), curve.name(), curve.description());
+    if !dev.cfg.displaypanel {
+        match StateSources::open(&dev.cfg, Path::new(sysfs_root()), &dev.bl).read(props) {
+            Some(r) => println!("screen:      {} ({}) from {}", r.state.name(), r.state.raw(), r.source.name()),
+            None => println!("screen:      unknown, no state source answers"),
+        }
+    }
+    let mut settings = SettingsReader::new(system_settings(), Duration::MAX, dev.cfg.debug);
+    let now = Instant::now();
+    let show = |v: Option<String>| v.unwrap_or_else(|| "unset".to_string());
+    println!("settings:    {}={} reduce_bright_colors_activated={} screen_brightness_mode={} low_power={}",
+        PANORAMIC_AOD, show(settings.get_secure(PANORAMIC_AOD, now)),
+        show(settings.get_secure("reduce_bright_colors_activated", now)),
+        show(settings.get_system("screen_brightness_mode", now)),
+        show(settings.get_global("low_power", now)));
+    let (source, level) = dev.current_input(props);
+    println!("source:      {}", source);
+    match level {
+        Some(v) => println!("input:       {} -> expected {}", v, scale(curve, v, dev.hw_min, dev.hw_max, dev.input_min, dev.input_max)),
+        None => println!("input:       unavailable"),
+    }
+    match read_file_int(dev.bl.brightness_path()) {
+        Some(v) => println!("output:      {}", v),
+        None => println!("output:      unreadable ({})", dev.bl.brightness_path().display()),
+    }
+    println!("config:");
+    for (key, val, src) in dev.cfg.entries() {
+        println!("  {} = {} ({})", key, val, src);
+    }
+    0
+}
+
+// what every curve would write for one input level
+pub fn map(props: &dyn PropertyStore, value: &str) -> i32 {
+    let dev = Device::load(props);
+    let Some(input) = dev.parse_input(value) else {
+        eprintln!("invalid input value '{}'", value);
+        return 2;
+    };
+    println!("input {} of {}-{} -> hw {}-{}", input, dev.input_min, dev.input_max, dev.hw_min, dev.hw_max);
+    for mode in ScalingMode::ALL {
+        let curve = dev.curves.get(mode);
+        let active = if mode == dev.mode { " *" } else { "" };
+        println!("  {:<18} {:<14} {}{}", mode_label(mode), curve.name(), scale(curve, input, dev.hw_min, dev.hw_max, dev.input_min, dev.input_max), active);
+    }
+    0
+}
+
+// one-shot write to the backlight node, the daemon takes over again on the next change
+pub fn set(props: &dyn PropertyStore, level: &str) -> i32 {
+    let dev = Device::load(props);
+    let Some(level) = level.parse::<i32>().ok().filter(|v| (0..=dev.hw_max).contains(v)) else {
+        eprintln!("hw level must be between 0 and {}", dev.hw_max);
+        return 2;
+    };
+    let path = dev.bl.brightness_path();
+    let file = match OpenOptions::new().write(true).open(&path) {
+        Ok(f) => f,
+        Err(e) => { eprintln!("could not open {}: {}", path.display(), e); return 1; },
+    };
+    let fmt = WriteFormat::from_config(&dev.cfg);
+    let prev = read_file_int(&path).unwrap_or(-1);
+    let mut last_val = -1;
+    if let Err(e) = write_brightness(file.as_raw_fd(), level, &fmt, &mut last_val, dev.cfg.debug) {
+        eprintln!("write to {} failed: {}", path.display(), e);
+        return 1;
+    }
+    println!("wrote {} to {}", level, path.display());
+    if let Some(mut rb) = Readback::from_config(&dev.cfg, &dev.bl) {
+        let out = rb.verify(file.as_raw_fd(), level, prev, &fmt, &std::thread::sleep, dev.cfg.debug);
+        println!("readback {}: {:?}", rb.path().display(), out);
+    }
+    0
+}
+
+// input -> hw table for the active curve, or the given mode
+pub fn dump_curve(props: &dyn PropertyStore, mode: Option<&str>) -> i32 {
+    let dev = Device::load(props);
+    let mode = match mode.map(ScalingMode::parse) {
+        None => dev.mode,
+        Some(Ok(m)) => m,
+        Some(Err(e)) => { eprintln!("{}", e); return 2; },
+    };
+    let curve = dev.curves.get(mode);
+    println!("# {} -> {}: {}", mode_label(mode), curve.name(), curve.description());
+    println!("# input\tpos\thw");
+    for (input, pos, hw) in curve_rows(&dev, mode) {
+        println!("{}\t{:.3}\t{}", input, pos, hw);
+    }
+    0
+}
+
+// (input, position, hw level) at DUMP_STEPS even steps over the input range
+fn curve_rows(dev: &Device, mode: ScalingMode) -> Vec<(i32, f32, i32)> {
+    let curve = dev.curves.get(mode);
+    (0..=DUMP_STEPS).map(|i| {
+        let input = dev.input_min + (dev.input_max - dev.input_min) * i / DUMP_STEPS;
+        let pos = input_to_pos(input, dev.input_min, dev.input_max);
+        (input, pos, pos_to_hw(curve, pos, dev.hw_min, dev.hw_max))
+    }).collect()
+}
+
+// sanity checks for a new port, exit code 1 if anything failed
+pub fn doctor(props: &dyn PropertyStore) -> i32 {
+    let dev = Device::load(props);
+    let mut failed = false;
+    let mut check = |ok: bool, warn_only: bool, msg: String| {
+        let tag = if ok { "OK  " } else if warn_only { "WARN" } else { "FAIL" };
+        if !ok && !warn_only { failed = true; }
+        println!("[{}] {}", tag, msg);
+    };
+
+    let found = discover(Path::new(sysfs_root()), dev.cfg.backlight_device.as_deref(), false).is_some();
+    check(found, false, format!("backlight device: {}", if found { dev.bl.dir.display().to_string() } else { "none found, using default path".to_string() }));
+    let writable = OpenOptions::new().write(true).open(dev.bl.brightness_path());
+    check(writable.is_ok(), false, match &writable {
+        Ok(_) => format!("{} is writable", dev.bl.brightness_path().display()),
+        Err(e) => format!("{} not writable: {}", dev.bl.brightness_path().display(), e),
+    });
+    check(dev.hw_min < dev.hw_max, false, format!("hw range {}-{}", dev.hw_min, dev.hw_max));
+    check(read_file_int(dev.bl.max_path()).is_some() || dev.cfg.hw_max.is_some(), true, format!("max level from {}", dev.bl.max_path().display()));
+    check(dev.input_min < dev.input_max, false, format!("input range {}-{}", dev.input_min, dev.input_max));
+    for entry in dev.cfg.mirrors.as_deref().map(mirror_entries).into_iter().flatten() {
+        match parse_mirror(entry) {
+            Ok(spec) => {
+                let node = spec.node();
+                let writable = OpenOptions::new().write(true).open(&node);
+                check(writable.is_ok(), true, match &writable {
+                    Ok(_) => format!("mirror {} is writable", node.display()),
+                    Err(e) => format!("mirror {} not writable: {}", node.display(), e),
+                });
+            },
+            Err(e) => check(false, true, format!("mirror skipped: {}", e)),
+        }
+    }
+    if let Some(rb) = Readback::from_config(&dev.cfg, &dev.bl) {
+        check(read_file_int(rb.path()).is_some(), false, format!("readback node {}", rb.path().display()));
+    }
+
+    let (source, level) = dev.current_input(props);
+    check(level.is_some(), true, format!("brightness from {}: {}", source, level.map_or("no value".to_string(), |v| v.to_string())));
+
+    if dev.cfg.displaypanel {
+        let oplus = Path::new(oplus_bright_path());
+        check(oplus.exists(), true, format!("oplus brightness file {}", oplus.display()));
+    } else {
+        let states = StateSources::open(&dev.cfg, Path::new(sysfs_root()), &dev.bl);
+        for kind in states.kinds() {
+            let state = states.read_one(props, kind);
+            check(state.is_some(), true, format!("screen state source {}: {}", kind.name(), state.map_or("no reading", |s| s.name())));
+        }
+        check(states.read(props).is_some(), false, format!("screen state from {}", states.describe()));
+        check(props.change_serial().is_some(), true, "property change notifications (polling otherwise)".to_string());
+        let dir = Path::new(settings_dir());
+        let secure = read_namespace(dir, Namespace::Secure);
+        check(secure.is_ok(), true, match &secure {
+            Ok(values) => format!("{} readable, {} secure settings", dir.join(file_name(Namespace::Secure)).display(), values.len()),
+            Err(e) => format!("{} unreadable ({}), using `settings list`", dir.join(file_name(Namespace::Secure)).display(), e),
+        });
+    }
+
+    if dev.mode == ScalingMode::Custom || dev.cfg.curve_points.is_some() {
+        let ok = load_custom_curve(&dev.cfg, dev.hw_min, dev.hw_max).is_some();
+        check(ok, dev.mode != ScalingMode::Custom, format!("custom curve {}", if ok { "valid" } else { "rejected, see logcat" }));
+    }
+
+    if failed { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+    use crate::paths::{persist_bright_mode_prop, persist_max, persist_min};
+    use crate::properties::MemoryProps;
+
+    // lcd-backlight at 1..2047 under a temp sysfs, input range 0..1000
+    fn device(tmp: &TempDir, mode: &str) -> Device {
+        let dir = tmp.path().join("class/leds/lcd-backlight");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("min_brightness"), "1").unwrap();
+        std::fs::write(dir.join("max_brightness"), "2047").unwrap();
+        std::fs::write(dir.join("brightness"), "0").unwrap();
+        let props = MemoryProps::with(&[(persist_bright_mode_prop(), mode), (persist_min(), "0"), (persist_max(), "1000")]);
+        Device::open(&props, Config::load_files(&props, &[]), tmp.path())
+    }
+
+    #[test]
+    fn parses_levels_and_fractions() {
+        let tmp = TempDir::new();
+        let dev = device(&tmp, "1");
+        assert_eq!((dev.hw_min, dev.hw_max, dev.input_min, dev.input_max), (1, 2047, 0, 1000));
+        // integers are input levels as is, a '.' makes a fraction of the input range
+        assert_eq!(dev.parse_input("500"), Some(500));
+        assert_eq!(dev.parse_input("1500"), Some(1500));
+        assert_eq!(dev.parse_input("0.25"), Some(250));
+        assert_eq!(dev.parse_input("1.0"), Some(1000));
+        // fractions are clamped to the range
+        assert_eq!(dev.parse_input("1.5"), Some(1000));
+        assert_eq!(dev.parse_input("-0.5"), Some(0));
+        for bad in ["", "half", "0.5.1", "2047x"] {
+            assert_eq!(dev.parse_input(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn dump_curve_rows_span_both_ranges() {
+        let tmp = TempDir::new();
+        let dev = device(&tmp, "1");
+        // the mode prop picks the active curve, dump-curve <mode> any other
+        assert_eq!(dev.mode, ScalingMode::Linear);
+        let rows = curve_rows(&dev, dev.mode);
+        assert_eq!(rows.len(), DUMP_STEPS as usize + 1);
+        assert_eq!(rows[0], (0, 0.0, 1));
+        assert_eq!(rows[10], (500, 0.5, 1024));
+        assert_eq!(rows[20], (1000, 1.0, 2047));
+        let curved = curve_rows(&dev, ScalingMode::Curved);
+        assert_eq!((curved[0], curved[20]), (rows[0], rows[20]));
+        assert!(curved[10].2 < rows[10].2);
+        assert!(rows.windows(2).all(|w| w[0].0 < w[1].0 && w[0].2 <= w[1].2));
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::constants::{READBACK_DELAY_MS, READBACK_RETRIES};
+use crate::curve::{ModeWatch, ScalingMode};
+use crate::logging::{log_d, log_e};
+use crate::properties::{PropertyStore, get_prop};
+use crate::paths::{
+    config_path, config_overlay_path, persist_dbg, is_oplus_panel_prop, persist_bright_mode_prop,
+    persist_isfloat_prop, persist_input_source_prop, persist_float_space_prop, persist_curve_points_prop, persist_curve_interp_prop,
+    persist_curve_gamma_prop, persist_curve_log_base_prop, persist_ramp_duration_prop,
+    persist_ramp_easing_prop, persist_ramp_frame_prop, persist_min, persist_max, persist_oplus_min, persist_oplus_max,
+    persist_custom_devmin_prop, persist_custom_devmax_prop, display_type_prop, persist_state_sources_prop,
+    persist_lux_aod_prop, persist_lux_aod_brightness_prop, persist_aod_policy_prop, backlight_override_prop,
+    persist_control_enable_prop, persist_control_socket_prop, control_socket_default, persist_readback_prop, persist_readback_retries_prop, persist_readback_delay_prop,
+    persist_write_format_prop, persist_write_scale_prop, persist_mirrors_prop, persist_settings_ttl_prop,
+};
+
+// config file key -> property key that overrides it
+type KeyProp = (&'static str, fn() -> &'static str);
+const KEYS: [KeyProp; 34] = [
+    ("debug", persist_dbg),
+    ("displaypanel", is_oplus_panel_prop),
+    ("brightness.mode", persist_bright_mode_prop),
+    ("brightness.isfloat", persist_isfloat_prop),
+    ("brightness.source", persist_input_source_prop),
+    ("brightness.float_space", persist_float_space_prop),
+    ("curve.points", persist_curve_points_prop),
+    ("curve.interp", persist_curve_interp_prop),
+    ("curve.gamma", persist_curve_gamma_prop),
+    ("curve.log_base", persist_curve_log_base_prop),
+    ("ramp.duration_ms", persist_ramp_duration_prop),
+    ("ramp.easing", persist_ramp_easing_prop),
+    ("ramp.frame_ms", persist_ramp_frame_prop),
+    ("range.min", persist_min),
+    ("range.max", persist_max),
+    ("displaypanel.min", persist_oplus_min),
+    ("displaypanel.max", persist_oplus_max),
+    ("hw.min", persist_custom_devmin_prop),
+    ("hw.max", persist_custom_devmax_prop),
+    ("display.type", display_type_prop),
+    ("display.state_sources", persist_state_sources_prop),
+    ("aod.lux", persist_lux_aod_prop),
+    ("aod.lux_brightness", persist_lux_aod_brightness_prop),
+    ("aod.policy", persist_aod_policy_prop),
+    ("backlight.device", backlight_override_prop),
+    ("backlight.readback", persist_readback_prop),
+    ("backlight.readback_retries", persist_readback_retries_prop),
+    ("backlight.readback_delay_ms", persist_readback_delay_prop),
+    ("backlight.format", persist_write_format_prop),
+    ("backlight.scale", persist_write_scale_prop),
+    ("backlight.mirrors", persist_mirrors_prop),
+    ("control.enable", persist_control_enable_prop),
+    ("control.socket", persist_control_socket_prop),
+    ("settings.ttl_ms", persist_settings_ttl_prop),
+];
+
+// where a resolved value came from, lowest precedence first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Source { Vendor, Overlay, Property }
+impl Source {
+    fn label(self) -> &'static str {
+        match self { Source::Vendor => "vendor", Source::Overlay => "overlay", Source::Property => "prop" }
+    }
+}
+
+// typed adaptor configuration
+#[derive(Clone, Debug)]
+pub(crate) struct Config {
+    pub(crate) debug: bool,
+    pub(crate) displaypanel: bool,
+    pub(crate) mode: ScalingMode,
+    pub(crate) float_input: bool,
+    pub(crate) input_source: Option<String>, // brightness input, see input.rs, None probes
+    pub(crate) float_space: Option<String>, // linear or slider, see hlg.rs
+    pub(crate) curve_points: Option<String>, // mode 2 curve, "in:out, ..."
+    pub(crate) curve_interp: Option<String>, // linear or cubic
+    pub(crate) curve_gamma: Option<f32>, // Curved mode exponent
+    pub(crate) curve_log_base: Option<f32>, // Log mode base
+    pub(crate) ramp_duration_ms: Option<u64>, // brightness ramp length, 0 = instant
+    pub(crate) ramp_easing: Option<String>, // linear, ease-out or exp
+    pub(crate) ramp_frame_ms: Option<u64>, // ramp frame interval
+    pub(crate) range_min: Option<i32>, // default mode input range
+    pub(crate) range_max: Option<i32>,
+    pub(crate) panel_min: Option<i32>, // DisplayPanel mode input range
+    pub(crate) panel_max: Option<i32>,
+    pub(crate) hw_min: Option<i32>, // overrides detected hw range
+    pub(crate) hw_max: Option<i32>,
+    pub(crate) ips: bool,
+    pub(crate) state_sources: Option<String>, // screen state sources by priority, "prop,drm,fb,bl_power"
+    pub(crate) lux_aod: bool,
+    pub(crate) lux_aod_brightness: Option<i32>,
+    pub(crate) aod_policy: Option<String>, // per-state overrides, "4:fixed:10, 1:keep"
+    pub(crate) backlight_device: Option<String>,
+    pub(crate) readback: Option<String>, // node to verify writes against, None when off
+    pub(crate) readback_retries: u32,
+    pub(crate) readback_delay_ms: u64, // before each readback
+    pub(crate) write_format: Option<String>, // decimal, newline, nul or hex
+    pub(crate) write_scale: Option<String>, // *N, /N, <<N or >>N
+    pub(crate) mirrors: Option<String>, // extra output nodes, see outputs.rs
+    pub(crate) control_socket: Option<String>, // None unless control.enable is set
+    pub(crate) settings_ttl_ms: Option<u64>, // android settings cache lifetime
+    raw: BTreeMap<String, (String, Source)>,
+}
+
+impl Config {
+    // precedence: built-in defaults < vendor file < /data overlay < properties
+    pub(crate) fn load(props: &dyn PropertyStore) -> Self {
+        Self::load_files(props, &[(Path::new(config_path()), Source::Vendor), (Path::new(config_overlay_path()), Source::Overlay)])
+    }
+
+    // same as load with explicit config files, lowest precedence first
+    pub(crate) fn load_files(props: &dyn PropertyStore, files: &[(&Path, Source)]) -> Self {
+        let mut raw = BTreeMap::new();
+        for (path, src) in files {
+            merge_file(&mut raw, path, *src);
+        }
+        for (key, prop) in KEYS.iter() {
+            if let Some(v) = get_prop(props, prop()) {
+                raw.insert(key.to_string(), (v, Source::Property));
+            }
+        }
+        let cfg = Self::from_raw(raw);
+        cfg.log_summary();
+        cfg
+    }
+
+    fn from_raw(raw: BTreeMap<String, (String, Source)>) -> Self {
+        let s = |k: &str| raw.get(k).map(|(v, _)| v.trim()).filter(|v| !v.is_empty());
+        let int = |k: &str| s(k).and_then(|v| v.parse::<i32>().ok());
+        let float = |k: &str| s(k).and_then(|v| v.parse::<f32>().ok());
+        // only "true", like the props always were read
+        let flag = |k: &str| s(k) == Some("true");
+        Self {
+            debug: flag("debug"),
+            displaypanel: flag("displaypanel"),
+            mode: match s("brightness.mode").map(ScalingMode::parse) {
+                None => ScalingMode::Curved,
+                Some(Ok(mode)) => mode,
+                Some(Err(e)) => { log_e(&format!("[Config] {}, using Curved", e)); ScalingMode::Curved },
+            },
+            float_input: flag("brightness.isfloat"),
+            input_source: s("brightness.source").map(str::to_string),
+            float_space: s("brightness.float_space").map(str::to_string),
+            curve_points: s("curve.points").map(str::to_string),
+            curve_interp: s("curve.interp").map(str::to_string),
+            curve_gamma: float("curve.gamma"),
+            curve_log_base: float("curve.log_base"),
+            ramp_duration_ms: s("ramp.duration_ms").and_then(|v| v.parse().ok()),
+            ramp_easing: s("ramp.easing").map(str::to_string),
+            ramp_frame_ms: s("ramp.frame_ms").and_then(|v| v.parse().ok()),
+            range_min: int("range.min"),
+            range_max: int("range.max"),
+            panel_min: int("displaypanel.min"),
+            panel_max: int("displaypanel.max"),
+            hw_min: int("hw.min").filter(|v| *v > 0),
+            hw_max: int("hw.max").filter(|v| *v > 0),
+            ips: s("display.type") == Some("IPS"),
+            state_sources: s("display.state_sources").map(str::to_string),
+            lux_aod: flag("aod.lux"),
+            lux_aod_brightness: int("aod.lux_brightness"),
+            aod_policy: s("aod.policy").map(str::to_string),
+            backlight_device: s("backlight.device").map(str::to_string),
+            readback: s("backlight.readback").filter(|v| !matches!(*v, "off" | "none")).map(str::to_string),
+            readback_retries: s("backlight.readback_retries").and_then(|v| v.parse().ok()).unwrap_or(READBACK_RETRIES),
+            readback_delay_ms: s("backlight.readback_delay_ms").and_then(|v| v.parse().ok()).unwrap_or(READBACK_DELAY_MS),
+            write_format: s("backlight.format").map(str::to_string),
+            write_scale: s("backlight.scale").map(str::to_string),
+            mirrors: s("backlight.mirrors").map(str::to_string),
+            // opt-in, the socket hands brightness to any root/system/shell client
+            control_socket: match s("control.socket") {
+                _ if !flag("control.enable") => None,
+                None => Some(control_socket_default().to_string()),
+                Some("off") | Some("none") => None,
+                Some(v) => Some(v.to_string()),
+            },
+            settings_ttl_ms: s("settings.ttl_ms").and_then(|v| v.parse().ok()),
+            raw,
+        }
+    }
+
+    // brightness mode can be switched at runtime through its property
+    pub(crate) fn live_mode(&self, props: &dyn PropertyStore, watch: &mut ModeWatch) -> ScalingMode {
+        watch.update(get_prop(props, persist_bright_mode_prop()), self.mode)
+    }
+
+    // every explicitly set key with its value and where it came from
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&str, &str, &'static str)> {
+        self.raw.iter().map(|(key, (val, src))| (key.as_str(), val.as_str(), src.label()))
+    }
+
+    fn log_summary(&self) {
+        log_d(&format!("[Config] Precedence: defaults < {} < {} < properties", config_path(), config_overlay_path()));
+        if !self.debug { return; }
+        for (key, val, src) in self.entries() {
+            log_d(&format!("[Config] {} = {} ({})", key, val, src));
+        }
+    }
+}
+
+// parse `key = value` lines, `[section]` headers prefix the keys below them
+pub(crate) fn parse_config(content: &str) -> Vec<(String, String)> {
+    let mut section = String::new();
+    let mut out = Vec::new();
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() { continue; }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+        let Some((key, val)) = line.split_once('=') else { continue; };
+        let key = key.trim();
+        let key = if section.is_empty() { key.to_string() } else { format!("{}.{}", section, key) };
+        out.push((key, val.trim().to_string()));
+    }
+    out
+}
+
+fn merge_file(raw: &mut BTreeMap<String, (String, Source)>, path: &Path, src: Source) {
+    let content = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => { log_e(&format!("[Config] Failed to read {}: {}", path.display(), e)); return; }
+    };
+    log_d(&format!("[Config] Loaded {}", path.display()));
+    for (key, val) in parse_config(&content) {
+        let known = KEYS.iter().any(|(k, _)| *k == key);
+        if !known {
+            log_e(&format!("[Config] {}: unknown key '{}'", path.display(), key));
+            continue;
+        }
+        raw.insert(key, (val, src));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+    use crate::properties::MemoryProps;
+
+    #[test]
+    fn parses_sections_and_comments() {
+        let parsed = parse_config("debug = true # trailing\n# whole line\n\n[brightness]\nmode=3\n  [ ramp ]  \nduration_ms = 100\nno equals sign\n");
+        let expected = [("debug", "true"), ("brightness.mode", "3"), ("ramp.duration_ms", "100")];
+        assert_eq!(parsed, expected.map(|(k, v)| (k.to_string(), v.to_string())));
+    }
+
+    #[test]
+    fn properties_beat_overlay_beat_vendor() {
+        let tmp = TempDir::new();
+        let vendor = tmp.path().join("vendor.conf");
+        let overlay = tmp.path().join("overlay.conf");
+        std::fs::write(&vendor, "[brightness]\nmode = 1\nisfloat = true\n[ramp]\nduration_ms = 100\nframe_ms = 20\n[bogus]\nkey = 1\n").unwrap();
+        std::fs::write(&overlay, "[ramp]\nduration_ms = 300\neasing = linear\n").unwrap();
+        let props = MemoryProps::with(&[("persist.sys.rianixia.ramp.easing", "exp")]);
+        let files = [(vendor.as_path(), Source::Vendor), (overlay.as_path(), Source::Overlay), (Path::new("/nonexistent.conf"), Source::Overlay)];
+        let cfg = Config::load_files(&props, &files);
+
+        assert_eq!((cfg.mode, cfg.float_input), (ScalingMode::Linear, true));
+        assert_eq!((cfg.ramp_duration_ms, cfg.ramp_frame_ms, cfg.ramp_easing.as_deref()), (Some(300), Some(20), Some("exp")));
+        // unknown keys are reported and dropped, sources are kept for `status`
+        let entries: Vec<_> = cfg.entries().collect();
+        assert!(!entries.iter().any(|(k, _, _)| k.starts_with("bogus")));
+        assert!(entries.contains(&("ramp.duration_ms", "300", "overlay")));
+        assert!(entries.contains(&("ramp.easing", "exp", "prop")));
+        assert!(entries.contains(&("ramp.frame_ms", "20", "vendor")));
+    }
+
+    #[test]
+    fn defaults_and_flags() {
+        let cfg = Config::load_files(&MemoryProps::new(), &[]);
+        assert_eq!((cfg.mode, cfg.debug, cfg.displaypanel, cfg.readback_retries), (ScalingMode::Curved, false, false, READBACK_RETRIES));
+        assert_eq!(cfg.control_socket, None);
+        let enabled = Config::load_files(&MemoryProps::with(&[("persist.sys.rianixia.control.enable", "true")]), &[]);
+        assert_eq!(enabled.control_socket.as_deref(), Some(control_socket_default()));
+
+        // flags are "true" only, off values and bad modes fall back
+        let props = MemoryProps::with(&[
+            ("persist.sys.rianixia.display-debug", "1"),
+            ("persist.sys.rianixia.brightness.mode", "9"),
+            ("persist.sys.rianixia.control.enable", "true"),
+            ("persist.sys.rianixia.control.socket", "off"),
+            ("persist.sys.rianixia.backlight.readback", "none"),
+        ]);
+        let cfg = Config::load_files(&props, &[]);
+        assert_eq!((cfg.debug, cfg.mode, cfg.control_socket, cfg.readback), (false, ScalingMode::Curved, None, None));
+    }
+}
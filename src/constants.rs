@@ -1,11 +1,26 @@
-use std::os::raw::c_int;
-
-// global constants
-pub(crate) const LOG_DEBUG: c_int = 3; // android log prio d
-pub(crate) const LOG_ERROR: c_int = 6; // android log prio e
-pub(crate) const FALLBACK_MAX: i32 = 8191; // fallback if max is null
-pub(crate) const FALLBACK_MIN: i32 = 222; // fallback if min is null
-pub(crate) const BRIGHTNESS_OFF: i32 = 0; // SCREEN OFF
-pub(crate) const OS14_MAX: i32 = 5118; // OS14 max fallback
-pub(crate) const OS14_MIN: i32 = 22; // OS14 min fallback
-
+use std::os::raw::c_int;
+
+// global constants
+pub(crate) const LOG_DEBUG: c_int = 3; // android log prio d
+pub(crate) const LOG_ERROR: c_int = 6; // android log prio e
+pub(crate) const FALLBACK_MAX: i32 = 8191; // fallback if max is null
+pub(crate) const FALLBACK_MIN: i32 = 222; // fallback if min is null
+pub(crate) const BRIGHTNESS_OFF: i32 = 0; // SCREEN OFF
+pub(crate) const OS14_MAX: i32 = 5118; // OS14 max fallback
+pub(crate) const OS14_MIN: i32 = 22; // OS14 min fallback
+pub(crate) const PROP_POLL_MS: u64 = 100; // poll interval when property waiting is unavailable
+pub(crate) const PROP_WAIT_ON_MS: u64 = 1000; // max wait for a property change, screen on
+pub(crate) const PROP_WAIT_IDLE_MS: u64 = 30000; // max wait for a property change, screen off/doze
+pub(crate) const STATE_POLL_MS: u64 = 250; // poll interval while the screen state comes from sysfs
+pub(crate) const INPUT_POLL_MS: u64 = 500; // poll interval for brightness from a sysfs node or a setting
+pub(crate) const RAMP_FRAME_MS: u64 = 33; // ramp frame interval
+pub(crate) const READBACK_RETRIES: u32 = 2; // rewrites when a readback doesn't match
+pub(crate) const READBACK_DELAY_MS: u64 = 5; // wait before reading back, drivers may apply levels asynchronously
+pub(crate) const REOPEN_AFTER_FAILURES: u32 = 3; // failed writes in a row before the node is reopened
+pub(crate) const REOPEN_BACKOFF_MIN_MS: u64 = 100; // first retry after a failed open or write
+pub(crate) const REOPEN_BACKOFF_MAX_MS: u64 = 5000; // backoff doubles up to this
+pub(crate) const SETTINGS_TTL_MS: u64 = 60000; // how long fetched android settings stay valid
+pub(crate) const STATUS_PUBLISH_MS: u64 = 1000; // min interval between status property updates
+pub(crate) const RAMP_DURATION_MS: u64 = 250; // default ramp length, 0 disables ramping
+pub(crate) const DEFAULT_GAMMA: f32 = 2.2; // Curved mode exponent
+pub(crate) const DEFAULT_LOG_BASE: f32 = 10.0; // Log mode base
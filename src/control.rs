@@ -0,0 +1,314 @@
+// control socket: a line protocol for querying and steering the running loop
+// status/last/subscribe are answered from the published snapshot,
+// everything else is queued for the loop and wakes it
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread::Scope;
+use std::time::{Duration, SystemTime};
+#[cfg(target_os = "android")]
+use std::os::android::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use crate::curve::ScalingMode;
+use crate::lease::{Lease, Leases, Level, SOCKET_CLIENT, SOCKET_PRIORITY};
+use crate::logging::{log_d, log_e};
+use crate::paths::control_wake_prop;
+use crate::properties::{PropertyStore, set_prop};
+use crate::status::Status;
+use crate::watch::WakeFd;
+
+// root, system and shell (adb) may talk to the socket
+const ALLOWED_UIDS: [u32; 3] = [0, 1000, 2000];
+const LINE_MAX: u64 = 256;
+// the loop never blocks longer than this on a subscriber
+const SUBSCRIBER_TIMEOUT: Duration = Duration::from_millis(50);
+const HELP: &str = "status | last | curve <mode|default> | override <hw-level> [ms] | override off | reload | subscribe";
+
+// requests the loop applies on its own thread
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Command {
+    Curve(Option<ScalingMode>), // pin a curve, None follows config again
+    Override(Option<(i32, Option<Duration>)>), // hw level held for a while (or until cleared), None releases it
+    Reload,
+}
+
+#[derive(Debug, PartialEq)]
+enum Request { Status, Last, Subscribe, Help, Apply(Command) }
+
+fn parse_request(line: &str) -> Result<Request, String> {
+    let mut words = line.split_whitespace();
+    let cmd = words.next().unwrap_or("");
+    let args: Vec<&str> = words.collect();
+    match (cmd, args.as_slice()) {
+        ("status", []) => Ok(Request::Status),
+        ("last", []) => Ok(Request::Last),
+        ("subscribe", []) => Ok(Request::Subscribe),
+        ("help", []) => Ok(Request::Help),
+        ("reload", []) => Ok(Request::Apply(Command::Reload)),
+        ("curve", ["default"]) => Ok(Request::Apply(Command::Curve(None))),
+        ("curve", [mode]) => ScalingMode::parse(mode).map(|m| Request::Apply(Command::Curve(Some(m)))),
+        ("override", ["off"]) => Ok(Request::Apply(Command::Override(None))),
+        ("override", [level, rest @ ..]) if rest.len() <= 1 => {
+            let level = level.parse::<i32>().ok().filter(|v| *v >= 0).ok_or_else(|| format!("invalid hw level '{}'", level))?;
+            let hold = match rest.first() {
+                None => None,
+                Some(ms) => Some(Duration::from_millis(ms.parse().map_err(|_| format!("invalid duration '{}'", ms))?)),
+            };
+            Ok(Request::Apply(Command::Override(Some((level, hold)))))
+        },
+        _ => Err(format!("unknown command '{}', try help", line)),
+    }
+}
+
+// shared between the loop and the socket threads
+pub(crate) struct Control {
+    status: Mutex<Status>,
+    pending: Mutex<Vec<Command>>,
+    subscribers: Mutex<Vec<UnixStream>>,
+    wake: WakeFd,
+    prop_wake: bool, // default mode blocks on properties, DisplayPanel mode on the wake fd
+    serial: AtomicU32,
+}
+
+impl Control {
+    pub(crate) fn new(prop_wake: bool) -> io::Result<Self> {
+        Ok(Self {
+            status: Mutex::new(Status::default()),
+            pending: Mutex::new(Vec::new()),
+            subscribers: Mutex::new(Vec::new()),
+            wake: WakeFd::new()?,
+            prop_wake,
+            serial: AtomicU32::new(0),
+        })
+    }
+
+    pub(crate) fn wake_fd(&self) -> RawFd { self.wake.raw() }
+
+    pub(crate) fn take(&self) -> Vec<Command> { std::mem::take(&mut *self.pending.lock().unwrap()) }
+
+    pub(crate) fn publish(&self, status: Status) { *self.status.lock().unwrap() = status; }
+
+    // push a write event to subscribers, stalled or closed ones are dropped
+    pub(crate) fn wrote(&self, val: i32) {
+        let mut subs = self.subscribers.lock().unwrap();
+        subs.retain_mut(|s| writeln!(s, "write {}", val).is_ok());
+    }
+
+    // queue a command without waking the loop
+    pub(crate) fn queue(&self, cmd: Command) { self.pending.lock().unwrap().push(cmd); }
+
+    fn submit(&self, props: &dyn PropertyStore, cmd: Command) {
+        self.queue(cmd);
+        self.wake.notify();
+        if self.prop_wake {
+            let n = self.serial.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+            set_prop(props, control_wake_prop(), &n.to_string());
+        }
+    }
+
+    fn subscribe(&self, stream: &UnixStream) -> io::Result<()> {
+        let s = stream.try_clone()?;
+        s.set_write_timeout(Some(SUBSCRIBER_TIMEOUT))?;
+        self.subscribers.lock().unwrap().push(s);
+        Ok(())
+    }
+}
+
+// "@name" binds an abstract socket, anything else a filesystem path
+pub(crate) fn bind(name: &str) -> io::Result<UnixListener> {
+    match name.strip_prefix('@') {
+        Some(abs) => UnixListener::bind_addr(&std::os::unix::net::SocketAddr::from_abstract_name(abs.as_bytes())?),
+        None => {
+            let _ = std::fs::remove_file(name);
+            UnixListener::bind(name)
+        },
+    }
+}
+
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED, &mut cred as *mut _ as *mut _, &mut len)
+    };
+    (res == 0).then_some(cred.uid)
+}
+
+// our own uid or one of ALLOWED_UIDS
+fn permitted(uid: u32) -> bool {
+    uid == unsafe { libc::getuid() } || ALLOWED_UIDS.contains(&uid)
+}
+
+// accept clients on a scoped thread, one thread per connection
+pub(crate) fn serve<'scope>(scope: &'scope Scope<'scope, '_>, listener: UnixListener, control: &'scope Control, props: &'scope (dyn PropertyStore + Sync)) {
+    scope.spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => { scope.spawn(move || handle(stream, control, props, permitted)); },
+                Err(e) => log_e(&format!("[Control] accept failed: {}", e)),
+            }
+        }
+    });
+}
+
+fn handle(mut stream: UnixStream, control: &Control, props: &dyn PropertyStore, allowed: fn(u32) -> bool) {
+    match peer_uid(&stream) {
+        Some(uid) if allowed(uid) => {},
+        uid => {
+            log_e(&format!("[Control] rejected client uid {:?}", uid));
+            let _ = writeln!(stream, "ERR permission denied");
+            return;
+        },
+    }
+    let Ok(read_half) = stream.try_clone() else { return; };
+    let mut reader = BufReader::new(read_half);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.by_ref().take(LINE_MAX).read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {},
+        }
+        let line = line.trim();
+        if line.is_empty() { continue; }
+        let reply = match parse_request(line) {
+            Ok(Request::Status) => {
+                let fields = control.status.lock().unwrap().fields();
+                let pairs: Vec<String> = fields.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                format!("OK {}", pairs.join(" "))
+            },
+            Ok(Request::Last) => format!("OK {}", control.status.lock().unwrap().last),
+            Ok(Request::Help) => format!("OK {}", HELP),
+            Ok(Request::Subscribe) => match control.subscribe(&stream) {
+                Ok(()) => "OK".to_string(),
+                Err(e) => format!("ERR {}", e),
+            },
+            Ok(Request::Apply(cmd)) => {
+                log_d(&format!("[Control] {:?}", cmd));
+                control.submit(props, cmd);
+                "OK".to_string()
+            },
+            Err(e) => format!("ERR {}", e),
+        };
+        if writeln!(stream, "{}", reply).is_err() { return; }
+    }
+}
+
+// what the loop holds above normal scaling: a curve pinned over the socket and override leases
+pub(crate) struct Held { pub(crate) mode: Option<ScalingMode>, pub(crate) leases: Leases }
+
+impl Held {
+    pub(crate) fn new(leases: Leases) -> Self { Self { mode: None, leases } }
+
+    // apply a queued command, Reload is left to the caller
+    // socket overrides are leases that beat every property lease
+    pub(crate) fn apply(&mut self, cmd: Command, now: SystemTime) {
+        match cmd {
+            Command::Curve(mode) => self.mode = mode,
+            Command::Override(Some((level, hold))) => self.leases.grant(Lease {
+                client: SOCKET_CLIENT.to_string(),
+                level: Level::Hw(level),
+                priority: SOCKET_PRIORITY,
+                expires: hold.map(|d| now + d),
+            }),
+            Command::Override(None) => self.leases.release(SOCKET_CLIENT),
+            Command::Reload => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use super::*;
+    use crate::harness::Harness;
+    use crate::paths::{persist_bright_mode_prop, persist_max, persist_min, persist_ramp_duration_prop};
+    use crate::properties::MemoryProps;
+
+    // screen on at 1000 on a 1..2047 panel with a linear curve and no ramp
+    fn device() -> Harness {
+        let h = Harness::new(1, 2047, 0);
+        h.prop(persist_bright_mode_prop(), "1").prop(persist_min(), "1").prop(persist_max(), "2047").prop(persist_ramp_duration_prop(), "0")
+            .prop("debug.tracing.screen_state", "2").prop("debug.tracing.screen_brightness", "1000");
+        h
+    }
+
+    fn send(client: &mut UnixStream, reader: &mut BufReader<UnixStream>, line: &str) -> String {
+        writeln!(client, "{}", line).unwrap();
+        recv(reader)
+    }
+
+    fn recv(reader: &mut BufReader<UnixStream>) -> String {
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        reply.trim_end().to_string()
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert_eq!(parse_request("status"), Ok(Request::Status));
+        assert_eq!(parse_request("curve 3"), Ok(Request::Apply(Command::Curve(Some(ScalingMode::CieLightness)))));
+        assert_eq!(parse_request("curve default"), Ok(Request::Apply(Command::Curve(None))));
+        assert_eq!(parse_request("override 2047 500"), Ok(Request::Apply(Command::Override(Some((2047, Some(Duration::from_millis(500))))))));
+        assert_eq!(parse_request("override  100"), Ok(Request::Apply(Command::Override(Some((100, None))))));
+        assert_eq!(parse_request("override off"), Ok(Request::Apply(Command::Override(None))));
+        for bad in ["", "curve 9", "override -1", "override 10 soon", "override 1 2 3", "status now", "reboot"] {
+            assert!(parse_request(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn rejects_peers_outside_the_allowed_uids() {
+        assert!(permitted(unsafe { libc::getuid() }));
+        assert!(permitted(1000) && permitted(2000));
+        let control = Control::new(false).unwrap();
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        thread::scope(|s| {
+            s.spawn(|| handle(server, &control, &MemoryProps::new(), |_| false));
+            assert_eq!(recv(&mut reader), "ERR permission denied");
+            // the connection is closed without reading anything
+            assert_eq!(recv(&mut reader), "");
+            let _ = writeln!(client, "override 2047");
+        });
+        assert_eq!(control.take(), vec![]);
+    }
+
+    #[test]
+    fn status_reply_from_the_last_pass() {
+        let h = device();
+        h.run_default(500);
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        thread::scope(|s| {
+            let control = h.control();
+            s.spawn(move || handle(server, control, &MemoryProps::new(), |_| true));
+            let reply = send(&mut client, &mut reader, "status");
+            assert!(reply.starts_with("OK mode=default curve=1:Linear hw_min=1 hw_max=2047 "), "{}", reply);
+            assert!(reply.contains(" last=1000 state=2 "), "{}", reply);
+            assert_eq!(send(&mut client, &mut reader, "last"), "OK 1000");
+            assert_eq!(send(&mut client, &mut reader, "curve 9"), "ERR unknown brightness mode '9'");
+            client.shutdown(std::net::Shutdown::Both).unwrap();
+        });
+    }
+
+    #[test]
+    fn subscribers_get_the_writes_of_a_pass() {
+        let h = device();
+        h.at(500, "debug.tracing.screen_brightness", "1500");
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        thread::scope(|s| {
+            let control = h.control();
+            s.spawn(move || handle(server, control, &MemoryProps::new(), |_| true));
+            assert_eq!(send(&mut client, &mut reader, "subscribe"), "OK");
+            h.run_default(1000);
+            assert_eq!(recv(&mut reader), "write 1000");
+            assert_eq!(recv(&mut reader), "write 1500");
+            client.shutdown(std::net::Shutdown::Both).unwrap();
+        });
+    }
+}
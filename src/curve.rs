@@ -0,0 +1,218 @@
+use crate::config::Config;
+use crate::logging::{log_d, log_e};
+use crate::scaling::{CieLightness, CustomCurve, Linear, Srgb, gamma_from_config, load_custom_curve, log_from_config};
+
+// brightness curve in normalized space: input fraction (0..1) -> output fraction (0..1)
+pub(crate) trait Curve: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> String;
+    fn forward(&self, x: f32) -> f32;
+    fn inverse(&self, y: f32) -> f32;
+}
+
+// numeric inverse for curves without a closed form, assumes forward is monotonic
+pub(crate) fn bisect_inverse(curve: &dyn Curve, y: f32) -> f32 {
+    let (mut lo, mut hi) = (0.0f32, 1.0f32);
+    for _ in 0..24 {
+        let mid = (lo + hi) / 2.0;
+        if curve.forward(mid) < y { lo = mid; } else { hi = mid; }
+    }
+    hi
+}
+
+// persist.sys.rianixia.brightness.mode values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ScalingMode { Curved, Linear, Custom, CieLightness, Srgb, Log }
+impl ScalingMode {
+    pub(crate) const ALL: [ScalingMode; 6] = [
+        ScalingMode::Curved, ScalingMode::Linear, ScalingMode::Custom,
+        ScalingMode::CieLightness, ScalingMode::Srgb, ScalingMode::Log,
+    ];
+
+    pub(crate) fn id(self) -> i32 {
+        match self {
+            ScalingMode::Curved => 0,
+            ScalingMode::Linear => 1,
+            ScalingMode::Custom => 2,
+            ScalingMode::CieLightness => 3,
+            ScalingMode::Srgb => 4,
+            ScalingMode::Log => 5,
+        }
+    }
+
+    pub(crate) fn from_id(id: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| m.id() == id)
+    }
+
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        s.parse::<i32>().ok().and_then(Self::from_id).ok_or_else(|| format!("unknown brightness mode '{}'", s))
+    }
+}
+
+// curve per scaling mode, the dispatcher only ever asks the registry
+pub(crate) struct CurveRegistry { curves: Vec<(ScalingMode, Box<dyn Curve>)> }
+impl CurveRegistry {
+    pub(crate) fn new() -> Self { Self { curves: Vec::new() } }
+
+    // built-in curves plus whatever the config defines for this hw range
+    pub(crate) fn load(cfg: &Config, hw_min: i32, hw_max: i32) -> Self {
+        Self::with_custom(cfg, load_custom_curve(cfg, hw_min, hw_max))
+    }
+
+    // built-in curves plus an already parsed custom curve, its points are fractions so any hw range can share it
+    pub(crate) fn with_custom(cfg: &Config, custom: Option<CustomCurve>) -> Self {
+        let mut reg = Self::new();
+        reg.register(ScalingMode::Curved, Box::new(gamma_from_config(cfg)));
+        reg.register(ScalingMode::Linear, Box::new(Linear));
+        reg.register(ScalingMode::CieLightness, Box::new(CieLightness));
+        reg.register(ScalingMode::Srgb, Box::new(Srgb));
+        reg.register(ScalingMode::Log, Box::new(log_from_config(cfg)));
+        if let Some(custom) = custom {
+            reg.register(ScalingMode::Custom, Box::new(custom));
+        }
+        if cfg.debug {
+            for (mode, curve) in &reg.curves {
+                log_d(&format!("[Curve] mode {} -> {}: {}", mode.id(), curve.name(), curve.description()));
+            }
+        }
+        reg
+    }
+
+    pub(crate) fn register(&mut self, mode: ScalingMode, curve: Box<dyn Curve>) {
+        self.curves.retain(|(m, _)| *m != mode);
+        self.curves.push((mode, curve));
+    }
+
+    // unregistered modes (e.g. a rejected custom curve) fall back to Curved
+    pub(crate) fn get(&self, mode: ScalingMode) -> &dyn Curve {
+        self.find(mode)
+            .or_else(|| self.find(ScalingMode::Curved))
+            .expect("Curved is always registered")
+    }
+
+    fn find(&self, mode: ScalingMode) -> Option<&dyn Curve> {
+        self.curves.iter().find(|(m, _)| *m == mode).map(|(_, c)| c.as_ref())
+    }
+}
+
+// tracks the live mode property, reports unknown values once per change
+pub(crate) struct ModeWatch { raw: Option<String>, mode: ScalingMode }
+impl ModeWatch {
+    pub(crate) fn new(mode: ScalingMode) -> Self { Self { raw: None, mode } }
+
+    pub(crate) fn update(&mut self, raw: Option<String>, fallback: ScalingMode) -> ScalingMode {
+        if raw == self.raw { return self.mode; }
+        self.mode = match raw.as_deref().map(ScalingMode::parse) {
+            None => fallback,
+            Some(Ok(mode)) => mode,
+            Some(Err(e)) => { log_e(&format!("[Curve] {}, using Curved", e)); ScalingMode::Curved },
+        };
+        self.raw = raw;
+        self.mode
+    }
+}
+
+// input level -> perceptual position (0..1)
+pub(crate) fn input_to_pos(val: i32, input_min: i32, input_max: i32) -> f32 {
+    if val <= input_min { return 0.0; }
+    if val >= input_max { return 1.0; }
+    (val - input_min) as f32 / (input_max - input_min) as f32
+}
+
+// perceptual position -> hw level through a curve, exact at both ends
+pub(crate) fn pos_to_hw(curve: &dyn Curve, pos: f32, hw_min: i32, hw_max: i32) -> i32 {
+    if pos <= 0.0 { return hw_min; }
+    if pos >= 1.0 { return hw_max; }
+    let range_hw = (hw_max - hw_min) as f32;
+    (hw_min as f32 + curve.forward(pos).clamp(0.0, 1.0) * range_hw).round() as i32
+}
+
+// hw level -> perceptual position, for picking up a level someone else wrote
+pub(crate) fn hw_to_pos(curve: &dyn Curve, hw: i32, hw_min: i32, hw_max: i32) -> f32 {
+    if hw <= hw_min { return 0.0; }
+    if hw >= hw_max { return 1.0; }
+    curve.inverse((hw - hw_min) as f32 / (hw_max - hw_min) as f32).clamp(0.0, 1.0)
+}
+
+// shared clamp/normalize: input level -> hw level through a curve
+pub(crate) fn scale(curve: &dyn Curve, val: i32, hw_min: i32, hw_max: i32, input_min: i32, input_max: i32) -> i32 {
+    pos_to_hw(curve, input_to_pos(val, input_min, input_max), hw_min, hw_max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::{persist_bright_mode_prop, persist_curve_points_prop};
+    use crate::properties::MemoryProps;
+
+    #[test]
+    fn parses_modes() {
+        for mode in ScalingMode::ALL {
+            assert_eq!(ScalingMode::parse(&mode.id().to_string()), Ok(mode));
+        }
+        assert_eq!(ScalingMode::parse(" 3 "), Ok(ScalingMode::CieLightness));
+        for bad in ["", "6", "-1", "linear", "1.0"] {
+            assert!(ScalingMode::parse(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(ScalingMode::from_id(7), None);
+
+        // the live mode prop: unset follows the config mode, unknown values mean Curved
+        let mut watch = ModeWatch::new(ScalingMode::Linear);
+        assert_eq!(watch.update(None, ScalingMode::Srgb), ScalingMode::Linear);
+        assert_eq!(watch.update(Some("5".to_string()), ScalingMode::Srgb), ScalingMode::Log);
+        assert_eq!(watch.update(Some("9".to_string()), ScalingMode::Srgb), ScalingMode::Curved);
+        assert_eq!(watch.update(None, ScalingMode::Srgb), ScalingMode::Srgb);
+    }
+
+    #[test]
+    fn registry_falls_back_to_curved() {
+        let props = MemoryProps::with(&[(persist_bright_mode_prop(), "2"), (persist_curve_points_prop(), "0:1, 1:2047")]);
+        let reg = CurveRegistry::load(&Config::load_files(&props, &[]), 1, 2047);
+        assert_eq!(reg.get(ScalingMode::Custom).name(), "Custom");
+        assert_eq!(reg.get(ScalingMode::CieLightness).name(), "CIE L*");
+
+        // rejected custom points leave Custom unregistered
+        let props = MemoryProps::with(&[(persist_bright_mode_prop(), "2"), (persist_curve_points_prop(), "1:1, 0:2047")]);
+        let mut reg = CurveRegistry::load(&Config::load_files(&props, &[]), 1, 2047);
+        assert_eq!(reg.get(ScalingMode::Custom).name(), "Curved");
+
+        // registering again replaces
+        reg.register(ScalingMode::Curved, Box::new(crate::scaling::Linear));
+        assert_eq!(reg.get(ScalingMode::Custom).name(), "Linear");
+    }
+
+    #[test]
+    fn every_curve_is_exact_at_the_ends_monotonic_and_invertible() {
+        let props = MemoryProps::with(&[(persist_curve_points_prop(), "0:0.0, 0.3:0.1, 0.7:0.5, 1:1.0")]);
+        let cfg = Config::load_files(&props, &[]);
+        for (hw_min, hw_max) in [(1, 2047), (22, 5118), (0, 1_000_000)] {
+            let reg = CurveRegistry::load(&cfg, hw_min, hw_max);
+            for mode in ScalingMode::ALL {
+                let curve = reg.get(mode);
+                assert_eq!(curve.name() == "Curved", matches!(mode, ScalingMode::Curved), "{:?} registered", mode);
+                assert_eq!(scale(curve, 222, hw_min, hw_max, 222, 8191), hw_min, "{:?}", mode);
+                assert_eq!(scale(curve, 8191, hw_min, hw_max, 222, 8191), hw_max, "{:?}", mode);
+                assert_eq!((pos_to_hw(curve, -0.5, hw_min, hw_max), pos_to_hw(curve, 1.5, hw_min, hw_max)), (hw_min, hw_max));
+
+                let mut prev = hw_min;
+                for level in 222..=8191 {
+                    let hw = scale(curve, level, hw_min, hw_max, 222, 8191);
+                    assert!(hw >= prev, "{:?} falls at input {}", mode, level);
+                    prev = hw;
+                }
+            }
+        }
+
+        // a fine hw range leaves only float error in the round trip
+        let reg = CurveRegistry::load(&cfg, 0, 1_000_000);
+        for mode in ScalingMode::ALL {
+            let curve = reg.get(mode);
+            for i in 0..=100 {
+                let pos = i as f32 / 100.0;
+                let back = hw_to_pos(curve, pos_to_hw(curve, pos, 0, 1_000_000), 0, 1_000_000);
+                assert!((back - pos).abs() < 5e-3, "{:?} {} -> {}", mode, pos, back);
+            }
+        }
+    }
+}
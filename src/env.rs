@@ -0,0 +1,75 @@
+use std::io;
+use std::os::fd::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
+use crate::config::Config;
+use crate::paths::{lease_store_path, oplus_bright_path, settings_dir, sysfs_root};
+use crate::properties::PropertyStore;
+use crate::settings::{CommandRunner, FileRunner, NoFallback, SettingsRunner};
+use crate::watch::{FileWatcher, PropWake};
+
+// blocks until a watched file changes, Ok(true) if it did
+pub(crate) trait FileWait {
+    fn wait(&self, timeout: Option<Duration>) -> io::Result<bool>;
+}
+impl FileWait for FileWatcher {
+    fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> { FileWatcher::wait(self, timeout) }
+}
+
+// everything the mode loops touch besides properties: time, paths, settings, config files
+// the real device uses SystemEnv, tests swap in a virtual clock and a temp sysfs
+pub(crate) trait Env {
+    fn now(&self) -> Instant;
+    fn wall_time(&self) -> SystemTime; // lease expiry, survives restarts unlike Instant
+    fn sleep(&self, d: Duration);
+    fn sysfs_root(&self) -> PathBuf;
+    fn oplus_path(&self) -> PathBuf;
+    fn lease_store(&self) -> PathBuf;
+    fn settings(&self) -> &dyn SettingsRunner; // android settings, read through a SettingsReader cache
+    fn settings_files(&self) -> &dyn SettingsRunner; // the settings files alone, for polled brightness
+    fn load_config(&self, props: &dyn PropertyStore) -> Config;
+
+    // watch a file, a write to the `wake` eventfd or a change to one of `props` also ends the wait
+    fn watch(&self, path: &Path, wake: Option<RawFd>, props: &[&'static str]) -> io::Result<Box<dyn FileWait + '_>>;
+
+    // called after every successful brightness write
+    fn wrote(&self, _val: i32) {}
+}
+
+// settings files first, `settings list` once they can't be read
+static SETTINGS: LazyLock<FileRunner<CommandRunner>> = LazyLock::new(|| FileRunner { dir: PathBuf::from(settings_dir()), fallback: CommandRunner });
+// a setting polled as the brightness source never spawns `settings`
+static SETTINGS_FILES: LazyLock<FileRunner<NoFallback>> = LazyLock::new(|| FileRunner { dir: PathBuf::from(settings_dir()), fallback: NoFallback });
+
+// android settings for one-shot readers outside the loop, e.g. the CLI
+pub(crate) fn system_settings() -> &'static dyn SettingsRunner { &*SETTINGS }
+
+// the device, watched properties are read from the loop's own store
+pub(crate) struct SystemEnv { props: Arc<dyn PropertyStore + Send + Sync> }
+impl SystemEnv {
+    pub(crate) fn new(props: Arc<dyn PropertyStore + Send + Sync>) -> Self { Self { props } }
+}
+impl Env for SystemEnv {
+    fn now(&self) -> Instant { Instant::now() }
+    fn wall_time(&self) -> SystemTime { SystemTime::now() }
+    fn sleep(&self, d: Duration) { sleep(d) }
+    fn sysfs_root(&self) -> PathBuf { PathBuf::from(sysfs_root()) }
+    fn oplus_path(&self) -> PathBuf { PathBuf::from(oplus_bright_path()) }
+    fn lease_store(&self) -> PathBuf { PathBuf::from(lease_store_path()) }
+    fn settings(&self) -> &dyn SettingsRunner { system_settings() }
+    fn settings_files(&self) -> &dyn SettingsRunner { &*SETTINGS_FILES }
+    fn load_config(&self, props: &dyn PropertyStore) -> Config { Config::load(props) }
+    fn watch(&self, path: &Path, wake: Option<RawFd>, props: &[&'static str]) -> io::Result<Box<dyn FileWait + '_>> {
+        let prop_wake = if props.is_empty() { None } else { Some(PropWake::spawn(self.props.clone(), props.to_vec())?) };
+        let wakes: Vec<RawFd> = wake.into_iter().chain(prop_wake.as_ref().map(PropWake::raw)).collect();
+        Ok(Box::new(SystemWatch { file: FileWatcher::new(path, &wakes)?, _props: prop_wake }))
+    }
+}
+
+// the file watcher and the helper thread feeding it property changes
+struct SystemWatch { file: FileWatcher, _props: Option<PropWake> }
+impl FileWait for SystemWatch {
+    fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> { self.file.wait(timeout) }
+}
@@ -1,9 +1,26 @@
-use std::os::raw::{c_int, c_char, c_uchar};
-
-// android ffi imports
-#[allow(dead_code)]
-unsafe extern "C" {
-    pub(crate) fn __system_property_get(name: *const c_uchar, value: *mut c_uchar) -> c_int;
-    pub(crate) fn __system_property_set(name: *const c_uchar, value: *const c_uchar) -> c_int;
-    pub(crate) fn __android_log_print(prio: c_int, tag: *const c_char, fmt: *const c_char, ...) -> c_int;
-}
\ No newline at end of file
+use std::ffi::CStr;
+use std::os::raw::{c_int, c_char, c_uchar, c_void};
+
+// android ffi imports
+#[allow(dead_code)]
+unsafe extern "C" {
+    pub(crate) fn __system_property_get(name: *const c_uchar, value: *mut c_uchar) -> c_int;
+    pub(crate) fn __system_property_set(name: *const c_uchar, value: *const c_uchar) -> c_int;
+    pub(crate) fn __system_property_find(name: *const c_char) -> *const c_void;
+    pub(crate) fn __system_property_serial(pi: *const c_void) -> u32;
+    pub(crate) fn __android_log_print(prio: c_int, tag: *const c_char, fmt: *const c_char, ...) -> c_int;
+}
+
+// api 26+ property change notification, resolved at runtime since we link against api 21
+pub(crate) type SystemPropertyAreaSerialFn = unsafe extern "C" fn() -> u32;
+pub(crate) type SystemPropertyWaitFn = unsafe extern "C" fn(
+    pi: *const c_void,
+    old_serial: u32,
+    new_serial: *mut u32,
+    relative_timeout: *const libc::timespec,
+) -> bool;
+
+pub(crate) fn dlsym_default(name: &CStr) -> Option<*mut c_void> {
+    let sym = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+    if sym.is_null() { None } else { Some(sym) }
+}
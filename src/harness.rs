@@ -0,0 +1,250 @@
+// deterministic harness for the mode loops: temp sysfs tree, scripted properties and a virtual clock
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::os::fd::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::config::Config;
+use crate::control::{Command, Control};
+use crate::env::{Env, FileWait};
+use crate::modes::{DefaultMode, PanelMode};
+use crate::properties::{MemoryProps, PropertyStore};
+use crate::settings::{Namespace, PANORAMIC_AOD, SettingsRunner};
+
+// passes before a run is considered stuck
+const MAX_PASSES: usize = 100_000;
+
+// temp directory removed on drop
+pub(crate) struct TempDir(PathBuf);
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!("xia-test-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path { &self.0 }
+}
+impl Drop for TempDir {
+    fn drop(&mut self) { let _ = std::fs::remove_dir_all(&self.0); }
+}
+
+enum Event { Prop(String, String), File(PathBuf, String), Control(Command) }
+
+// scripted device: props and files change at virtual times, waits advance the clock to the next change
+pub(crate) struct Harness {
+    tmp: TempDir,
+    props: MemoryProps,
+    base: Instant,
+    now: Cell<Duration>,
+    until: Cell<Duration>,
+    events: RefCell<VecDeque<(Duration, Event)>>,
+    panoramic: Cell<bool>,
+    settings_reads: Cell<usize>,
+    system_reads: Cell<usize>,
+    passes: Cell<usize>,
+    control: Control,
+    writes: RefCell<Vec<(u64, i32)>>,
+}
+
+impl Harness {
+    // lcd-backlight with the given hw range, currently at `level`
+    pub(crate) fn new(hw_min: i32, hw_max: i32, level: i32) -> Self {
+        let h = Self {
+            tmp: TempDir::new(),
+            props: MemoryProps::new(),
+            base: Instant::now(),
+            now: Cell::new(Duration::ZERO),
+            until: Cell::new(Duration::ZERO),
+            events: RefCell::new(VecDeque::new()),
+            panoramic: Cell::new(false),
+            settings_reads: Cell::new(0),
+            system_reads: Cell::new(0),
+            passes: Cell::new(0),
+            control: Control::new(false).unwrap(),
+            writes: RefCell::new(Vec::new()),
+        };
+        let dir = h.backlight_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("min_brightness"), hw_min.to_string()).unwrap();
+        std::fs::write(dir.join("max_brightness"), hw_max.to_string()).unwrap();
+        std::fs::write(dir.join("brightness"), level.to_string()).unwrap();
+        h
+    }
+
+    // temp root everything lives under
+    pub(crate) fn root(&self) -> &Path { self.tmp.path() }
+
+    pub(crate) fn backlight_dir(&self) -> PathBuf { self.tmp.path().join("sys/class/leds/lcd-backlight") }
+
+    // set a property before the run starts
+    pub(crate) fn prop(&self, key: &str, val: &str) -> &Self {
+        self.props.set(key, val);
+        self
+    }
+
+    pub(crate) fn set_panoramic(&self, on: bool) -> &Self {
+        self.panoramic.set(on);
+        self
+    }
+
+    // how often the loop fetched secure settings, where panoramic AOD lives
+    pub(crate) fn settings_reads(&self) -> usize { self.settings_reads.get() }
+
+    // system settings fetches, where a setting brightness source lives
+    pub(crate) fn system_reads(&self) -> usize { self.system_reads.get() }
+
+    // loop passes run so far, every wait that ended early adds one
+    pub(crate) fn passes(&self) -> usize { self.passes.get() }
+
+    // schedule a property change at `ms` virtual milliseconds
+    pub(crate) fn at(&self, ms: u64, key: &str, val: &str) -> &Self {
+        self.schedule(ms, Event::Prop(key.to_string(), val.to_string()))
+    }
+
+    // schedule a rewrite of the oplus brightness file at `ms`
+    pub(crate) fn at_oplus(&self, ms: u64, val: i32) -> &Self {
+        self.schedule(ms, Event::File(self.oplus_path(), val.to_string()))
+    }
+
+    // schedule an outside write to the backlight brightness node at `ms`
+    pub(crate) fn at_node(&self, ms: u64, val: i32) -> &Self {
+        self.schedule(ms, Event::File(self.backlight_dir().join("brightness"), val.to_string()))
+    }
+
+    // schedule a write to any file, e.g. a sysfs state node, at `ms`
+    pub(crate) fn at_file(&self, ms: u64, path: &Path, val: &str) -> &Self {
+        self.schedule(ms, Event::File(path.to_path_buf(), val.to_string()))
+    }
+
+    // schedule a control socket command at `ms`
+    pub(crate) fn at_control(&self, ms: u64, cmd: Command) -> &Self {
+        self.schedule(ms, Event::Control(cmd))
+    }
+
+    fn schedule(&self, ms: u64, ev: Event) -> &Self {
+        let t = Duration::from_millis(ms);
+        let mut events = self.events.borrow_mut();
+        let idx = events.iter().position(|(at, _)| *at > t).unwrap_or(events.len());
+        events.insert(idx, (t, ev));
+        self
+    }
+
+    // the control the runs report to, for driving it like a socket client
+    pub(crate) fn control(&self) -> &Control { &self.control }
+
+    // config from the harness props only, no vendor or overlay file
+    pub(crate) fn config(&self) -> Config { Config::load_files(self, &[]) }
+
+    // run default mode until `ms`, returns the values written
+    pub(crate) fn run_default(&self, ms: u64) -> Vec<i32> {
+        let cfg = self.config();
+        let mut m = DefaultMode::start(self, self, cfg, Some(&self.control));
+        self.run_until(ms, || m.pass());
+        self.values()
+    }
+
+    // run DisplayPanel mode until `ms`, returns the values written
+    pub(crate) fn run_panel(&self, ms: u64) -> Vec<i32> {
+        let cfg = self.config();
+        let mut m = PanelMode::start(self, self, cfg, Some(&self.control));
+        self.run_until(ms, || m.pass());
+        self.values()
+    }
+
+    fn run_until(&self, ms: u64, mut pass: impl FnMut()) {
+        self.until.set(Duration::from_millis(ms));
+        for _ in 0..MAX_PASSES {
+            if self.now.get() >= self.until.get() { return; }
+            pass();
+            self.passes.set(self.passes.get() + 1);
+        }
+        panic!("loop did not reach {}ms after {} passes", ms, MAX_PASSES);
+    }
+
+    // written values with their virtual time in ms
+    pub(crate) fn writes(&self) -> Vec<(u64, i32)> { self.writes.borrow().clone() }
+    pub(crate) fn values(&self) -> Vec<i32> { self.writes.borrow().iter().map(|(_, v)| *v).collect() }
+
+    // apply everything due at or before `t`
+    fn advance_to(&self, t: Duration) {
+        self.now.set(self.now.get().max(t));
+        loop {
+            let mut events = self.events.borrow_mut();
+            if events.front().is_none_or(|(at, _)| *at > self.now.get()) { return; }
+            let (_, ev) = events.pop_front().unwrap();
+            drop(events);
+            match ev {
+                Event::Prop(k, v) => { self.props.set(&k, &v); },
+                Event::File(p, v) => std::fs::write(p, v).unwrap(),
+                Event::Control(cmd) => self.control.queue(cmd),
+            }
+        }
+    }
+
+    // wait up to `timeout` (None = until the end of the run), true if something changed
+    // file writes and socket commands always end it, of the props only `keys`, the others are applied along the way
+    fn wait(&self, timeout: Option<Duration>, keys: &[&str]) -> bool {
+        let wakes = |ev: &Event| match ev { Event::Prop(k, _) => keys.contains(&k.as_str()), _ => true };
+        let end = self.until.get();
+        let deadline = timeout.map_or(end, |t| (self.now.get() + t).min(end));
+        let next = self.events.borrow().iter().take_while(|(at, _)| *at <= deadline).find(|(_, ev)| wakes(ev)).map(|(at, _)| *at);
+        match next {
+            Some(at) => { self.advance_to(at); true },
+            None => { self.advance_to(deadline); false },
+        }
+    }
+}
+
+impl PropertyStore for Harness {
+    fn get(&self, key: &str) -> Option<String> { self.props.get(key) }
+    fn set(&self, key: &str, val: &str) -> bool { self.props.set(key, val) }
+    fn change_serial(&self) -> Option<u32> { self.props.change_serial() }
+
+    // like bionic, other props are applied without ending the wait
+    fn wait_change(&self, serial: Option<u32>, keys: &[&str], timeout: Duration) -> bool {
+        if serial.is_some() && serial != self.props.change_serial() { return true; }
+        self.wait(Some(timeout), keys)
+    }
+}
+
+// only panoramic AOD is set, secure and system lists are counted
+impl SettingsRunner for Harness {
+    fn list(&self, ns: Namespace) -> io::Result<HashMap<String, String>> {
+        let mut values = HashMap::new();
+        if ns == Namespace::Secure {
+            self.settings_reads.set(self.settings_reads.get() + 1);
+            values.insert(PANORAMIC_AOD.to_string(), u8::from(self.panoramic.get()).to_string());
+        } else if ns == Namespace::System {
+            self.system_reads.set(self.system_reads.get() + 1);
+        }
+        Ok(values)
+    }
+}
+
+struct HarnessWait<'a>(&'a Harness, Vec<&'static str>);
+impl FileWait for HarnessWait<'_> {
+    fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> { Ok(self.0.wait(timeout, &self.1)) }
+}
+
+impl Env for Harness {
+    fn now(&self) -> Instant { self.base + self.now.get() }
+    fn wall_time(&self) -> SystemTime { UNIX_EPOCH + Duration::from_secs(1_000_000) + self.now.get() }
+    fn sleep(&self, d: Duration) { self.advance_to(self.now.get() + d) }
+    fn sysfs_root(&self) -> PathBuf { self.tmp.path().join("sys") }
+    fn oplus_path(&self) -> PathBuf { self.tmp.path().join("oplus_brightness") }
+    fn lease_store(&self) -> PathBuf { self.tmp.path().join("leases") }
+    fn settings(&self) -> &dyn SettingsRunner { self }
+    fn settings_files(&self) -> &dyn SettingsRunner { self }
+    fn load_config(&self, props: &dyn PropertyStore) -> Config { Config::load_files(props, &[]) }
+    fn watch(&self, _path: &Path, _wake: Option<RawFd>, props: &[&'static str]) -> io::Result<Box<dyn FileWait + '_>> {
+        Ok(Box::new(HarnessWait(self, props.to_vec())))
+    }
+
+    fn wrote(&self, val: i32) {
+        self.writes.borrow_mut().push((self.now.get().as_millis() as u64, val));
+    }
+}
@@ -0,0 +1,121 @@
+// android's BrightnessUtils: the brightness slider is in HLG gamma space, float brightness is linear
+// a slider at 50% is about 8% linear, scaling the slider position instead matches how stock devices feel
+use crate::config::Config;
+use crate::logging::log_e;
+
+// HLG (ARIB STD-B67) constants, verbatim from the framework
+const R: f32 = 0.5;
+#[allow(clippy::excessive_precision)]
+const A: f32 = 0.17883277;
+#[allow(clippy::excessive_precision)]
+const B: f32 = 0.28466892;
+#[allow(clippy::excessive_precision)]
+const C: f32 = 0.55991073;
+
+// linear brightness (0..1) -> slider position (0..1), convertLinearToGammaFloat with min 0, max 1
+pub(crate) fn linear_to_gamma(v: f32) -> f32 {
+    let n = v.clamp(0.0, 1.0) * 12.0;
+    let ret = if n <= 1.0 { n.sqrt() * R } else { A * (n - B).ln() + C };
+    ret.clamp(0.0, 1.0)
+}
+
+// slider position (0..1) -> linear brightness (0..1), convertGammaToLinearFloat with min 0, max 1
+// HLG spans 0..12, nothing reads a slider position yet
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn gamma_to_linear(g: f32) -> f32 {
+    let x = g.clamp(0.0, 1.0);
+    let ret = if x <= R { (x / R).powi(2) } else { ((x - C) / A).exp() + B };
+    ret.clamp(0.0, 12.0) / 12.0
+}
+
+// what float inputs are scaled as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FloatSpace {
+    Linear, // the framework's float as is
+    Slider, // converted to the slider position first
+}
+
+impl FloatSpace {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "linear" => Some(FloatSpace::Linear),
+            "slider" => Some(FloatSpace::Slider),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        match cfg.float_space.as_deref() {
+            None => FloatSpace::Linear,
+            Some(s) => FloatSpace::parse(s).unwrap_or_else(|| {
+                log_e(&format!("[Input] Unknown float space '{}', using linear", s));
+                FloatSpace::Linear
+            }),
+        }
+    }
+
+    // a float input fraction in the space it gets scaled in
+    pub(crate) fn apply(self, f: f32) -> f32 {
+        match self { FloatSpace::Linear => f, FloatSpace::Slider => linear_to_gamma(f) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the framework rounds slider positions to 0..GAMMA_SPACE_MAX
+    const GAMMA_SPACE_MAX: f32 = 65535.0;
+    fn slider(v: f32) -> i32 { (linear_to_gamma(v) * GAMMA_SPACE_MAX).round() as i32 }
+
+    #[test]
+    fn matches_framework_reference_values() {
+        // BrightnessUtils.convertLinearToGammaFloat(v, 0f, 1f)
+        for (linear, expected) in [
+            (0.0, 0), (0.01, 11351), (0.05, 25382), (1.0 / 12.0, 32768), (0.1, 35657),
+            (0.25, 48401), (0.5, 57123), (0.75, 62068), (1.0, 65535),
+        ] {
+            assert!((slider(linear) - expected).abs() <= 1, "{} -> {} != {}", linear, slider(linear), expected);
+        }
+
+        // rising everywhere and continuous across the knee at R
+        let slider_at = |i: i32| linear_to_gamma(i as f32 / 1000.0);
+        for i in 1..=1000 {
+            assert!(slider_at(i) > slider_at(i - 1), "{}", i);
+        }
+        for v in [1.0 / 12.0 - 1e-4, 1.0 / 12.0 + 1e-4] {
+            assert!((linear_to_gamma(v) - R).abs() < 1e-3, "{}", v);
+        }
+    }
+
+    #[test]
+    fn gamma_to_linear_matches_framework_and_round_trips() {
+        // BrightnessUtils.convertGammaToLinearFloat(g / GAMMA_SPACE_MAX, 0f, 1f)
+        for (gamma, expected) in [
+            (0, 0.0), (6554, 0.003334), (16384, 0.020834), (32768, 0.083336),
+            (39321, 0.127996), (49152, 0.264978), (58982, 0.581861), (65535, 1.0),
+        ] {
+            let linear = gamma_to_linear(gamma as f32 / GAMMA_SPACE_MAX);
+            assert!((linear - expected).abs() < 1e-5, "{} -> {} != {}", gamma, linear, expected);
+        }
+
+        // both directions undo each other, across the knee at R too
+        for i in 0..=1000 {
+            let x = i as f32 / 1000.0;
+            assert!((linear_to_gamma(gamma_to_linear(x)) - x).abs() < 1e-4, "slider {}", x);
+            assert!((gamma_to_linear(linear_to_gamma(x)) - x).abs() < 1e-4, "linear {}", x);
+        }
+        assert_eq!(gamma_to_linear(-0.5), 0.0);
+        assert_eq!(gamma_to_linear(1.5), 1.0);
+    }
+
+    #[test]
+    fn float_spaces() {
+        assert_eq!(FloatSpace::parse("slider"), Some(FloatSpace::Slider));
+        assert_eq!(FloatSpace::parse(" linear "), Some(FloatSpace::Linear));
+        assert_eq!(FloatSpace::parse("hlg"), None);
+        assert_eq!(FloatSpace::Linear.apply(0.25), 0.25);
+        assert_eq!(FloatSpace::Slider.apply(0.0), 0.0);
+        assert!((FloatSpace::Slider.apply(1.0 / 12.0) - 0.5).abs() < 1e-6);
+    }
+}
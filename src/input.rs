@@ -0,0 +1,319 @@
+// where the framework's brightness level comes from: the tracing prop, the oplus file, a sysfs shadow node or an android setting
+// `brightness.source` picks one, `auto` probes the usual ones at startup and tells float from int by the values
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::config::Config;
+use crate::constants::INPUT_POLL_MS;
+use crate::hlg::FloatSpace;
+use crate::logging::{log_d, log_e};
+use crate::properties::{PropertyStore, get_prop};
+use crate::settings::{Namespace, SettingsReader, SettingsRunner};
+
+pub(crate) const BRIGHTNESS_PROP: &str = "debug.tracing.screen_brightness";
+const SETTING_FLOAT: &str = "screen_brightness_float"; // system, same float the tracing prop carries
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum InputKind {
+    Prop,                       // debug.tracing.screen_brightness
+    Oplus(PathBuf),             // oplus_brightness file, DisplayPanel mode
+    Node(PathBuf),              // a sysfs node shadowing the framework level
+    Setting(Namespace, String), // an android setting, re-read every INPUT_POLL_MS
+}
+
+impl InputKind {
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            InputKind::Prop => format!("prop {}", BRIGHTNESS_PROP),
+            InputKind::Oplus(p) => format!("oplus {}", p.display()),
+            InputKind::Node(p) => format!("node {}", p.display()),
+            InputKind::Setting(ns, key) => format!("setting {}/{}", ns.name(), key),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ValueFormat {
+    Int,     // input level as is, "1000" or "1000.0"
+    Float,   // fraction of the input range, 0.0-1.0
+    Percent, // percent of the input range
+}
+
+impl ValueFormat {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "int" => Some(ValueFormat::Int),
+            "float" => Some(ValueFormat::Float),
+            "percent" => Some(ValueFormat::Percent),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self { ValueFormat::Int => "int", ValueFormat::Float => "float", ValueFormat::Percent => "percent" }
+    }
+
+    // only values one format can produce decide: a fraction below 1.0 is float, a whole number above 1 is int
+    // 0 and 1 could be either and so could a fraction above 1 like the lux AOD marker, those are read as int meanwhile
+    // percent looks like int, it has to be configured
+    fn detect(raw: &str) -> Option<Self> {
+        let v = raw.trim().parse::<f32>().ok()?;
+        if v > 0.0 && v < 1.0 {
+            Some(ValueFormat::Float)
+        } else if v > 1.0 && v.fract() == 0.0 {
+            Some(ValueFormat::Int)
+        } else {
+            None
+        }
+    }
+}
+
+// "prop[:fmt]", "oplus[:fmt]", "node:<path>[:fmt]" or "setting:<namespace>/<key>[:fmt]", fmt is int, float or percent
+pub(crate) fn parse_source(spec: &str, oplus: &Path) -> Result<(InputKind, Option<ValueFormat>), String> {
+    let spec = spec.trim();
+    let (body, format) = match spec.rsplit_once(':').and_then(|(b, f)| Some((b, ValueFormat::parse(f)?))) {
+        Some((b, f)) => (b, Some(f)),
+        None => (spec, None),
+    };
+    let kind = match body.split_once(':') {
+        None if body == "prop" => InputKind::Prop,
+        None if body == "oplus" => InputKind::Oplus(oplus.to_path_buf()),
+        Some(("node", path)) if path.starts_with('/') => InputKind::Node(PathBuf::from(path)),
+        Some(("setting", rest)) => {
+            let (ns, key) = rest.split_once('/')
+                .and_then(|(ns, key)| Some((Namespace::parse(ns)?, key)))
+                .filter(|(_, key)| !key.is_empty())
+                .ok_or_else(|| format!("invalid setting '{}', expected <secure|system|global>/<key>", rest))?;
+            InputKind::Setting(ns, key.to_string())
+        },
+        _ => return Err(format!("unknown brightness source '{}'", spec)),
+    };
+    Ok((kind, format))
+}
+
+// files may carry a newline or NUL
+fn clean(raw: &str) -> &str { raw.trim_matches(|c: char| c.is_whitespace() || c == '\0') }
+
+fn int_part(raw: &str) -> Option<i32> {
+    raw.chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse().ok()
+}
+
+pub(crate) struct InputSource<'a> {
+    kind: InputKind,
+    format: Option<ValueFormat>, // None until a value gives it away
+    settings: SettingsReader<'a>, // only used by setting sources
+    space: FloatSpace, // what float values are scaled as
+}
+
+impl<'a> InputSource<'a> {
+    fn new(kind: InputKind, format: Option<ValueFormat>, runner: &'a dyn SettingsRunner, dbg: bool) -> Self {
+        Self { kind, format, settings: SettingsReader::new(runner, Duration::from_millis(INPUT_POLL_MS), dbg), space: FloatSpace::Linear }
+    }
+
+    // the configured source, or the first live one of the usual ones for the mode, logged either way
+    pub(crate) fn open(cfg: &Config, props: &dyn PropertyStore, runner: &'a dyn SettingsRunner, oplus: &Path, now: Instant) -> Self {
+        let configured = cfg.input_source.as_deref().filter(|s| *s != "auto")
+            .and_then(|s| parse_source(s, oplus).map_err(|e| log_e(&format!("[Input] {}, probing instead", e))).ok());
+        let explicit = configured.as_ref().and_then(|(_, f)| *f);
+        let mut src = match configured {
+            Some((kind, format)) => Self::new(kind, format, runner, cfg.debug),
+            None => {
+                let candidates = if cfg.displaypanel {
+                    vec![InputKind::Oplus(oplus.to_path_buf())]
+                } else {
+                    vec![InputKind::Prop, InputKind::Setting(Namespace::System, SETTING_FLOAT.to_string())]
+                };
+                Self::probe(candidates, props, runner, cfg.debug, now)
+            },
+        };
+        // the old switch still forces float tracing values over what the probe saw
+        if cfg.float_input && src.kind == InputKind::Prop && explicit.is_none() { src.format = Some(ValueFormat::Float); }
+        src.space = FloatSpace::from_config(cfg);
+        log_d(&format!("[Input] Brightness from {}", src.describe()));
+        src
+    }
+
+    // first candidate with a numeric value, formats detected from what's there
+    fn probe(candidates: Vec<InputKind>, props: &dyn PropertyStore, runner: &'a dyn SettingsRunner, dbg: bool, now: Instant) -> Self {
+        let mut chosen: Option<Self> = None;
+        let mut fallback = None;
+        for kind in candidates {
+            let mut src = Self::new(kind, None, runner, dbg);
+            let raw = src.raw(props, now).filter(|v| clean(v).parse::<f32>().is_ok());
+            src.format = raw.as_deref().map(clean).and_then(ValueFormat::detect);
+            match &raw {
+                Some(v) => log_d(&format!("[Input] Probe: {} is live, '{}' ({})", src.kind.describe(), clean(v), src.format.map_or("format unknown yet", ValueFormat::name))),
+                None => log_d(&format!("[Input] Probe: {} has no value", src.kind.describe())),
+            }
+            if raw.is_some() && chosen.is_none() {
+                chosen = Some(src);
+            } else if fallback.is_none() {
+                fallback = Some(src);
+            }
+        }
+        chosen.or(fallback).expect("no brightness source candidates")
+    }
+
+    pub(crate) fn kind(&self) -> &InputKind { &self.kind }
+
+    pub(crate) fn describe(&self) -> String {
+        let space = if self.space == FloatSpace::Slider { ", scaled in slider space" } else { "" };
+        format!("{} ({}{})", self.kind.describe(), self.format.map_or("format unknown yet", ValueFormat::name), space)
+    }
+
+    // prop changes and oplus file writes wake the loop, nodes and settings have to be polled
+    pub(crate) fn polled(&self) -> bool { matches!(self.kind, InputKind::Node(_) | InputKind::Setting(..)) }
+
+    fn raw(&mut self, props: &dyn PropertyStore, now: Instant) -> Option<String> {
+        match &self.kind {
+            InputKind::Prop => get_prop(props, BRIGHTNESS_PROP),
+            InputKind::Oplus(path) | InputKind::Node(path) => std::fs::read_to_string(path).ok(),
+            InputKind::Setting(ns, key) => self.settings.get(*ns, key, now),
+        }
+    }
+
+    // the level in min..=max, Some(0) when the framework reports 0, None when there's nothing to read
+    pub(crate) fn read(&mut self, props: &dyn PropertyStore, min: i32, max: i32, now: Instant) -> Option<i32> {
+        let raw = self.raw(props, now)?;
+        let raw = clean(&raw);
+        if self.format.is_none() && let Some(f) = ValueFormat::detect(raw) {
+            log_d(&format!("[Input] {} values are {}", self.kind.describe(), f.name()));
+            self.format = Some(f);
+        }
+        let fraction = |f: f32| -> i32 {
+            if f == 0.0 { 0 } else { (min as f32 + f.clamp(0.0, 1.0) * (max - min) as f32).round() as i32 }
+        };
+        match self.format.unwrap_or(ValueFormat::Int) {
+            ValueFormat::Int => int_part(raw),
+            ValueFormat::Float => raw.parse::<f32>().ok().map(|f| fraction(self.space.apply(f))),
+            ValueFormat::Percent => raw.parse::<f32>().ok().map(|p| fraction(p / 100.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io;
+    use crate::harness::TempDir;
+    use crate::properties::MemoryProps;
+
+    struct Fake(&'static str);
+    impl SettingsRunner for Fake {
+        fn list(&self, ns: Namespace) -> io::Result<HashMap<String, String>> {
+            let mut values = HashMap::new();
+            if ns == Namespace::System && !self.0.is_empty() { values.insert(SETTING_FLOAT.to_string(), self.0.to_string()); }
+            Ok(values)
+        }
+    }
+
+    fn open<'a>(props: &MemoryProps, runner: &'a Fake, oplus: &Path) -> InputSource<'a> {
+        InputSource::open(&Config::load_files(props, &[]), props, runner, oplus, Instant::now())
+    }
+
+    #[test]
+    fn parses_sources() {
+        let oplus = Path::new("/data/oplus");
+        assert_eq!(parse_source("prop", oplus), Ok((InputKind::Prop, None)));
+        assert_eq!(parse_source("prop:percent", oplus), Ok((InputKind::Prop, Some(ValueFormat::Percent))));
+        assert_eq!(parse_source("oplus", oplus), Ok((InputKind::Oplus(oplus.to_path_buf()), None)));
+        assert_eq!(parse_source("node:/sys/x/shadow:float", oplus), Ok((InputKind::Node(PathBuf::from("/sys/x/shadow")), Some(ValueFormat::Float))));
+        assert_eq!(parse_source("setting:system/screen_brightness", oplus), Ok((InputKind::Setting(Namespace::System, "screen_brightness".to_string()), None)));
+        for bad in ["", "tracing", "node:relative", "setting:system", "setting:vendor/x", "setting:system/", "prop:hex"] {
+            assert!(parse_source(bad, oplus).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn probe_picks_the_first_live_source_and_detects_the_format() {
+        let oplus = Path::new("/nonexistent/oplus");
+        let props = MemoryProps::new();
+        let settings = Fake("0.5");
+        // no prop, the settings float takes over
+        let mut src = open(&props, &settings, oplus);
+        assert_eq!(src.kind(), &InputKind::Setting(Namespace::System, SETTING_FLOAT.to_string()));
+        assert_eq!(src.read(&props, 0, 2000, Instant::now()), Some(1000));
+
+        // a live prop wins, float vs int from its values
+        props.set(BRIGHTNESS_PROP, "0.25");
+        let mut src = open(&props, &settings, oplus);
+        assert_eq!(src.kind(), &InputKind::Prop);
+        assert_eq!(src.read(&props, 0, 2000, Instant::now()), Some(500));
+        props.set(BRIGHTNESS_PROP, "0.0");
+        assert_eq!(src.read(&props, 0, 2000, Instant::now()), Some(0));
+
+        // 0 says nothing, the first real value decides
+        let mut src = open(&props, &Fake(""), oplus);
+        assert_eq!(src.describe(), "prop debug.tracing.screen_brightness (format unknown yet)");
+        props.set(BRIGHTNESS_PROP, "1500.0");
+        assert_eq!(src.read(&props, 0, 2000, Instant::now()), Some(1500));
+        assert_eq!(src.describe(), "prop debug.tracing.screen_brightness (int)");
+
+        // nothing live at all still reads the prop once it shows up
+        let empty = MemoryProps::new();
+        let mut src = open(&empty, &Fake(""), oplus);
+        assert_eq!(src.kind(), &InputKind::Prop);
+        assert_eq!(src.read(&empty, 0, 2000, Instant::now()), None);
+    }
+
+    #[test]
+    fn ambiguous_values_keep_detecting() {
+        let oplus = Path::new("/nonexistent/oplus");
+        let now = Instant::now();
+
+        // an int source at its lowest level spelled "1.0" isn't taken for full float brightness
+        let props = MemoryProps::with(&[(BRIGHTNESS_PROP, "1.0")]);
+        let mut src = open(&props, &Fake(""), oplus);
+        assert_eq!(src.read(&props, 0, 2000, now), Some(1));
+        assert_eq!(src.describe(), "prop debug.tracing.screen_brightness (format unknown yet)");
+        props.set(BRIGHTNESS_PROP, "1500.0");
+        assert_eq!(src.read(&props, 0, 2000, now), Some(1500));
+        props.set(BRIGHTNESS_PROP, "1.0");
+        assert_eq!(src.read(&props, 0, 2000, now), Some(1));
+        assert_eq!(src.describe(), "prop debug.tracing.screen_brightness (int)");
+
+        // a float source starting on the lux AOD marker isn't locked to int
+        let props = MemoryProps::with(&[(BRIGHTNESS_PROP, "2937.773")]);
+        let mut src = open(&props, &Fake(""), oplus);
+        assert_eq!(src.describe(), "prop debug.tracing.screen_brightness (format unknown yet)");
+        props.set(BRIGHTNESS_PROP, "0.5");
+        assert_eq!(src.read(&props, 0, 2000, now), Some(1000));
+        props.set(BRIGHTNESS_PROP, "1.0");
+        assert_eq!(src.read(&props, 0, 2000, now), Some(2000));
+        assert_eq!(src.describe(), "prop debug.tracing.screen_brightness (float)");
+    }
+
+    #[test]
+    fn configured_nodes_and_formats() {
+        let tmp = TempDir::new();
+        let node = tmp.path().join("shadow");
+        std::fs::write(&node, "40\n").unwrap();
+        let props = MemoryProps::new();
+        props.set("persist.sys.rianixia.brightness.source", &format!("node:{}:percent", node.display()));
+        let mut src = open(&props, &Fake(""), tmp.path());
+        assert!(src.polled());
+        assert_eq!(src.read(&props, 100, 1100, Instant::now()), Some(500));
+
+        // isfloat still forces float on the prop
+        let props = MemoryProps::with(&[("persist.sys.rianixia.brightness.isfloat", "true"), (BRIGHTNESS_PROP, "1")]);
+        let mut src = open(&props, &Fake(""), tmp.path());
+        assert_eq!(src.read(&props, 0, 2000, Instant::now()), Some(2000));
+
+        // slider space scales the framework's linear float by slider position
+        props.set("persist.sys.rianixia.brightness.float_space", "slider");
+        props.set(BRIGHTNESS_PROP, &(1.0f32 / 12.0).to_string());
+        let mut src = open(&props, &Fake(""), tmp.path());
+        assert_eq!(src.describe(), "prop debug.tracing.screen_brightness (float, scaled in slider space)");
+        assert_eq!(src.read(&props, 0, 2000, Instant::now()), Some(1000));
+        props.set(BRIGHTNESS_PROP, "0.0");
+        assert_eq!(src.read(&props, 0, 2000, Instant::now()), Some(0));
+
+        // DisplayPanel mode reads the oplus file, NUL padded or not
+        let oplus = tmp.path().join("oplus_brightness");
+        std::fs::write(&oplus, "1023\0").unwrap();
+        let props = MemoryProps::with(&[("persist.sys.rianixia.is-displaypanel.support", "true")]);
+        let mut src = open(&props, &Fake(""), &oplus);
+        assert!(!src.polled());
+        assert_eq!(src.read(&props, 22, 5118, Instant::now()), Some(1023));
+    }
+}
@@ -0,0 +1,231 @@
+// brightness override leases: other processes hold a level above normal scaling for a while
+// requests come in through a property, the table is persisted so a restart keeps live leases
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::logging::{log_d, log_e};
+
+// socket overrides beat every property lease
+pub(crate) const SOCKET_PRIORITY: i32 = 1000;
+pub(crate) const SOCKET_CLIENT: &str = "socket";
+const CLIENT_MAX: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Level { Hw(i32), Input(i32) }
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Lease {
+    pub(crate) client: String,
+    pub(crate) level: Level,
+    pub(crate) priority: i32,
+    pub(crate) expires: Option<SystemTime>, // None holds until released, never persisted
+}
+
+// what the winning lease means for one pass
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Hold { Idle, Active(i32), Ended(i32) }
+
+fn valid_client(c: &str) -> bool {
+    !c.is_empty() && c.len() <= CLIENT_MAX && c.chars().all(|ch| ch.is_ascii_alphanumeric() || "_.-".contains(ch))
+}
+
+// request property value:
+//   <client>:<hw|input>:<level>:<ms>[:<priority>]   grant or replace
+//   <client>:release                               drop
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Request { Grant(Lease), Release(String) }
+
+pub(crate) fn parse_request(s: &str, now: SystemTime) -> Result<Request, String> {
+    let parts: Vec<&str> = s.trim().split(':').map(str::trim).collect();
+    let client = parts[0];
+    if !valid_client(client) { return Err(format!("invalid client name '{}'", client)); }
+    match parts.as_slice() {
+        [_, "release"] => Ok(Request::Release(client.to_string())),
+        [_, kind, level, ms, rest @ ..] if rest.len() <= 1 => {
+            let level = level.parse::<i32>().ok().filter(|v| *v >= 0).ok_or_else(|| format!("invalid level '{}'", level))?;
+            let level = match *kind {
+                "hw" => Level::Hw(level),
+                "input" => Level::Input(level),
+                _ => return Err(format!("unknown level kind '{}', expected hw or input", kind)),
+            };
+            let ms = ms.parse::<u64>().ok().filter(|v| *v > 0).ok_or_else(|| format!("invalid duration '{}'", ms))?;
+            let priority = match rest.first() {
+                None => 0,
+                Some(p) => p.parse::<i32>().ok().filter(|v| *v < SOCKET_PRIORITY).ok_or_else(|| format!("invalid priority '{}'", p))?,
+            };
+            Ok(Request::Grant(Lease { client: client.to_string(), level, priority, expires: Some(now + Duration::from_millis(ms)) }))
+        },
+        _ => Err(format!("malformed lease request '{}'", s)),
+    }
+}
+
+fn epoch_ms(t: SystemTime) -> u64 { t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64) }
+
+// one lease per line: client kind level priority expiry (unix ms)
+fn format_lease(l: &Lease) -> Option<String> {
+    let (kind, level) = match l.level { Level::Hw(v) => ("hw", v), Level::Input(v) => ("input", v) };
+    Some(format!("{} {} {} {} {}", l.client, kind, level, l.priority, epoch_ms(l.expires?)))
+}
+
+fn parse_lease(line: &str) -> Option<Lease> {
+    let f: Vec<&str> = line.split_whitespace().collect();
+    let [client, kind, level, priority, expires] = f.as_slice() else { return None; };
+    if !valid_client(client) { return None; }
+    let level = level.parse().ok()?;
+    Some(Lease {
+        client: client.to_string(),
+        level: match *kind { "hw" => Level::Hw(level), "input" => Level::Input(level), _ => return None },
+        priority: priority.parse().ok()?,
+        expires: Some(UNIX_EPOCH + Duration::from_millis(expires.parse().ok()?)),
+    })
+}
+
+pub(crate) struct Leases { list: Vec<Lease>, store: PathBuf, active: Option<i32> }
+
+impl Leases {
+    // restore persisted leases, dropping the ones that ran out while we were down
+    pub(crate) fn load(store: PathBuf, now: SystemTime) -> Self {
+        let mut list = Vec::new();
+        let mut dirty = false;
+        if let Ok(content) = std::fs::read_to_string(&store) {
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                match parse_lease(line) {
+                    Some(l) if l.expires.is_some_and(|e| e > now) => {
+                        log_d(&format!("[Lease] Restored '{}' {:?} prio {}", l.client, l.level, l.priority));
+                        list.push(l);
+                    },
+                    Some(l) => { log_d(&format!("[Lease] Dropping stale lease '{}', it expired while the adaptor was down", l.client)); dirty = true; },
+                    None => { log_e(&format!("[Lease] Dropping unreadable entry '{}' in {}", line, store.display())); dirty = true; },
+                }
+            }
+        }
+        let leases = Self { list, store, active: None };
+        if dirty { leases.save(); }
+        leases
+    }
+
+    fn save(&self) {
+        let content: String = self.list.iter().filter_map(format_lease).map(|l| l + "\n").collect();
+        let tmp = self.store.with_extension("tmp");
+        let res = if content.is_empty() {
+            match std::fs::remove_file(&self.store) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        } else {
+            std::fs::write(&tmp, content).and_then(|_| std::fs::rename(&tmp, &self.store))
+        };
+        if let Err(e) = res { log_e(&format!("[Lease] Failed to save {}: {}", self.store.display(), e)); }
+    }
+
+    // handle a raw request property value
+    pub(crate) fn request(&mut self, raw: &str, now: SystemTime) {
+        match parse_request(raw, now) {
+            Ok(Request::Grant(l)) => self.grant(l),
+            Ok(Request::Release(c)) => self.release(&c),
+            Err(e) => log_e(&format!("[Lease] {}", e)),
+        }
+    }
+
+    // add a lease, replacing the client's previous one
+    pub(crate) fn grant(&mut self, lease: Lease) {
+        log_d(&format!("[Lease] '{}' holds {:?} prio {}", lease.client, lease.level, lease.priority));
+        self.list.retain(|l| l.client != lease.client);
+        self.list.push(lease);
+        self.save();
+    }
+
+    pub(crate) fn release(&mut self, client: &str) {
+        let before = self.list.len();
+        self.list.retain(|l| l.client != client);
+        if self.list.len() != before {
+            log_d(&format!("[Lease] '{}' released", client));
+            self.save();
+        }
+    }
+
+    // highest priority wins, the newest among equals
+    fn winner(&self) -> Option<&Lease> {
+        self.list.iter().max_by_key(|l| l.priority)
+    }
+
+    // expire old leases and report the winning level in hw units
+    pub(crate) fn poll(&mut self, now: SystemTime, to_hw: impl Fn(Level) -> i32) -> Hold {
+        let before = self.list.len();
+        self.list.retain(|l| {
+            let live = l.expires.is_none_or(|e| e > now);
+            if !live { log_d(&format!("[Lease] '{}' expired", l.client)); }
+            live
+        });
+        if self.list.len() != before { self.save(); }
+
+        match (self.winner().map(|l| to_hw(l.level)), self.active) {
+            (Some(hw), _) => { self.active = Some(hw); Hold::Active(hw) },
+            (None, Some(prev)) => { self.active = None; Hold::Ended(prev) },
+            (None, None) => Hold::Idle,
+        }
+    }
+
+    // hw level of the winning lease as of the last poll
+    pub(crate) fn level(&self) -> Option<i32> { self.active }
+
+    // time until the next lease expires
+    pub(crate) fn remaining(&self, now: SystemTime) -> Option<Duration> {
+        self.list.iter().filter_map(|l| l.expires).min().map(|e| e.duration_since(now).unwrap_or(Duration::ZERO))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+
+    fn at(ms: u64) -> SystemTime { UNIX_EPOCH + Duration::from_secs(1_000_000) + Duration::from_millis(ms) }
+
+    fn lease(client: &str, level: Level, priority: i32, expires_ms: u64) -> Lease {
+        Lease { client: client.to_string(), level, priority, expires: Some(at(expires_ms)) }
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert_eq!(parse_request("qr:hw:2047:5000:10", at(0)), Ok(Request::Grant(lease("qr", Level::Hw(2047), 10, 5000))));
+        assert_eq!(parse_request("factory:input:4095:100", at(0)), Ok(Request::Grant(lease("factory", Level::Input(4095), 0, 100))));
+        assert_eq!(parse_request("qr:release", at(0)), Ok(Request::Release("qr".to_string())));
+        for bad in ["", "qr", "q r:hw:1:1", "qr:lux:1:100", "qr:hw:-1:100", "qr:hw:1:0", "qr:hw:1:100:1000", "qr:hw:1:100:1:2"] {
+            assert!(parse_request(bad, at(0)).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn highest_priority_wins_then_ramps_back() {
+        let tmp = TempDir::new();
+        let mut leases = Leases::load(tmp.path().join("leases"), at(0));
+        let hw = |l: Level| match l { Level::Hw(v) => v, Level::Input(v) => v / 2 };
+        leases.grant(lease("low", Level::Hw(100), 0, 1000));
+        leases.grant(lease("high", Level::Input(800), 5, 500));
+        leases.grant(lease("tie", Level::Hw(300), 0, 2000));
+        assert_eq!(leases.poll(at(0), hw), Hold::Active(400));
+        assert_eq!(leases.poll(at(500), hw), Hold::Active(300));
+        leases.release("tie");
+        assert_eq!(leases.poll(at(600), hw), Hold::Active(100));
+        assert_eq!(leases.remaining(at(600)), Some(Duration::from_millis(400)));
+        assert_eq!(leases.poll(at(1000), hw), Hold::Ended(100));
+        assert_eq!(leases.poll(at(1001), hw), Hold::Idle);
+        assert!(!tmp.path().join("leases").exists());
+    }
+
+    #[test]
+    fn restart_keeps_live_leases_and_drops_stale_ones() {
+        let tmp = TempDir::new();
+        let store = tmp.path().join("leases");
+        let mut leases = Leases::load(store.clone(), at(0));
+        leases.grant(lease("short", Level::Hw(10), 0, 100));
+        leases.grant(lease("long", Level::Hw(20), 0, 5000));
+        leases.grant(Lease { client: SOCKET_CLIENT.to_string(), level: Level::Hw(30), priority: SOCKET_PRIORITY, expires: None });
+        std::fs::write(&store, std::fs::read_to_string(&store).unwrap() + "garbage\n").unwrap();
+
+        let mut restored = Leases::load(store.clone(), at(1000));
+        assert_eq!(restored.list, vec![lease("long", Level::Hw(20), 0, 5000)]);
+        assert_eq!(std::fs::read_to_string(&store).unwrap().lines().count(), 1);
+        assert_eq!(restored.poll(at(1000), |_| 20), Hold::Active(20));
+    }
+}
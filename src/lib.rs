@@ -1,12 +1,34 @@
-mod constants;
-mod ffi;
-mod logging;
-mod paths;
-mod properties;
-mod range;
-mod scaling;
-mod state;
-mod utils;
-mod writer;
-mod modes;
-pub use modes::run;
+mod animation;
+mod backlight;
+pub mod cli;
+mod config;
+mod constants;
+mod control;
+mod curve;
+mod env;
+mod hlg;
+mod input;
+mod lease;
+#[cfg(target_os = "android")]
+mod ffi; // bionic/liblog only exist on device
+mod logging;
+mod outputs;
+mod paths;
+mod policy;
+mod properties;
+mod range;
+mod scaling;
+mod settings;
+mod settings_xml;
+mod state;
+mod status;
+mod utils;
+mod watch;
+mod writer;
+mod modes;
+#[cfg(test)]
+mod harness;
+pub use modes::{run, run_with_props};
+pub use properties::{PropertyStore, MemoryProps, FileProps, system_props};
+#[cfg(target_os = "android")]
+pub use properties::BionicProps;
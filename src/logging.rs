@@ -1,15 +1,23 @@
-use std::ffi::CString;
-use std::os::raw::c_int;
-use crate::ffi::__android_log_print;
-use crate::paths::log_tag;
-use crate::constants::{LOG_DEBUG, LOG_ERROR};
-
-// logging utilities
-pub(crate) fn log_write(level: c_int, msg: &str) {
-    let tag = CString::new(log_tag()).unwrap();
-    let fmt = CString::new("%s").unwrap();
-    let c_msg = CString::new(msg).unwrap();
-    unsafe { __android_log_print(level, tag.as_ptr(), fmt.as_ptr(), c_msg.as_ptr()) };
-}
-pub(crate) fn log_d(msg: &str) { log_write(LOG_DEBUG, msg); }
-pub(crate) fn log_e(msg: &str) { log_write(LOG_ERROR, msg); }
+use std::os::raw::c_int;
+use crate::paths::log_tag;
+use crate::constants::{LOG_DEBUG, LOG_ERROR};
+
+// logging utilities
+#[cfg(target_os = "android")]
+pub(crate) fn log_write(level: c_int, msg: &str) {
+    use std::ffi::CString;
+    use crate::ffi::__android_log_print;
+    let tag = CString::new(log_tag()).unwrap();
+    let fmt = CString::new("%s").unwrap();
+    let c_msg = CString::new(msg).unwrap();
+    unsafe { __android_log_print(level, tag.as_ptr(), fmt.as_ptr(), c_msg.as_ptr()) };
+}
+
+// off device, logcat style lines on stderr
+#[cfg(not(target_os = "android"))]
+pub(crate) fn log_write(level: c_int, msg: &str) {
+    let prio = if level >= LOG_ERROR { 'E' } else { 'D' };
+    eprintln!("{} {}: {}", prio, log_tag(), msg);
+}
+pub(crate) fn log_d(msg: &str) { log_write(LOG_DEBUG, msg); }
+pub(crate) fn log_e(msg: &str) { log_write(LOG_ERROR, msg); }
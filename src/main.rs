@@ -1,3 +1,31 @@
-fn main() {
-    oplusbright::run();
-}
\ No newline at end of file
+use std::process::exit;
+use oplusbright::{cli, system_props};
+
+const USAGE: &str = "usage: xia_display_adaptor [command]
+
+commands:
+  run                 start the adaptor (default)
+  status              resolved config, hw range, current input and output
+  map <value>         what each curve writes for an input level (or 0.0-1.0 fraction)
+  set <hw-level>      write one level to the backlight node
+  dump-curve [mode]   input -> hw table for the active (or given) mode
+  doctor              check the device setup";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let store = system_props();
+    let props = store.as_ref();
+    let code = match args.as_slice() {
+        [] | ["run"] => { oplusbright::run_with_props(store.into()); 0 },
+        ["status"] => cli::status(props),
+        ["map", value] => cli::map(props, value),
+        ["set", level] => cli::set(props, level),
+        ["dump-curve"] => cli::dump_curve(props, None),
+        ["dump-curve", mode] => cli::dump_curve(props, Some(mode)),
+        ["doctor"] => cli::doctor(props),
+        ["help" | "-h" | "--help"] => { println!("{}", USAGE); 0 },
+        _ => { eprintln!("{}", USAGE); 2 },
+    };
+    exit(code);
+}
@@ -1,278 +1,854 @@
-use std::fs::OpenOptions;
-use std::os::unix::io::AsRawFd;
-use std::time::Duration;
-use std::{thread::sleep};
-
-use crate::constants::{BRIGHTNESS_OFF, FALLBACK_MIN, OS14_MIN, OS14_MAX};
-use crate::logging::{log_d, log_e};
-use crate::properties::{get_prop, get_prop_int};
-use crate::paths::{
-    is_oplus_panel_prop, oplus_bright_path, min_bright_path, persist_oplus_min,
-    persist_oplus_max, bright_path, persist_dbg, display_type_prop,
-    persist_lux_aod_prop, persist_bright_mode_prop, persist_lux_aod_brightness_prop,
-};
-use crate::utils::{read_file_int, get_max_brightness, get_min_brightness, is_panoramic_aod_enabled};
-use crate::scaling::{scale_brightness_linear, scale_brightness_curved, scale_brightness_custom};
-use crate::range::BrightnessRange;
-use crate::state::{get_prop_brightness, get_screen_state};
-use crate::writer::write_brightness;
-
-// debug check
-pub(crate) fn dbg_on() -> bool {
-    get_prop(persist_dbg()).as_deref() == Some("true")
-}
-
-// mode checks
-pub(crate) fn is_oplus_panel_mode() -> bool {
-    get_prop(is_oplus_panel_prop()).as_deref() == Some("true")
-}
-pub(crate) fn is_float_mode() -> bool {
-    get_prop("persist.sys.rianixia.brightness.isfloat").as_deref() == Some("true")
-}
-pub(crate) fn is_ips_mode() -> bool {
-    get_prop(display_type_prop()).as_deref() == Some("IPS")
-}
-pub(crate) fn is_lux_aod_mode() -> bool {
-    get_prop(persist_lux_aod_prop()).as_deref() == Some("true")
-}
-
-// 0 = Curved, 1 = Linear, 2 = Custom
-pub(crate) fn get_brightness_mode() -> i32 {
-    get_prop_int(persist_bright_mode_prop()).unwrap_or(0)
-}
-
-// main dispatcher
-pub fn run() {
-    if is_oplus_panel_mode() {
-        run_oplus_panel_mode();
-    } else {
-        run_default_mode();
-    }
-}
-
-// DisplayPanel mode (os14 and under)
-fn run_oplus_panel_mode() {
-    let dbg = dbg_on();
-    if dbg { log_d("[DisplayAdaptor] Starting in DisplayPanel Mode..."); }
-
-    let oplus_path_str = oplus_bright_path();
-    if !std::path::Path::new(oplus_path_str).exists() {
-        if dbg { log_d(&format!("[DisplayPanel Mode] File {} not found, attempting to create it.", oplus_path_str)); }
-        loop {
-            match std::fs::File::create(oplus_path_str) {
-                Ok(_) => {
-                    if dbg { log_d(&format!("[DisplayPanel Mode] Successfully created {}.", oplus_path_str)); }
-                    break;
-                },
-                Err(e) => {
-                    log_e(&format!("[DisplayPanel Mode] Failed to create {}, retrying in 1s: {}", oplus_path_str, e));
-                    sleep(Duration::from_secs(1));
-                }
-            }
-        }
-    }
-    let hw_min = get_min_brightness(dbg);
-    let hw_max = get_max_brightness(dbg);
-
-    let input_min = get_prop_int(persist_oplus_min()).unwrap_or(OS14_MIN);
-    let input_max = get_prop_int(persist_oplus_max()).unwrap_or(OS14_MAX);
-    if dbg { log_d(&format!("[DisplayPanel Mode] Scaling range: {}-{} -> {}-{}", input_min, input_max, hw_min, hw_max)); }
-
-    let file = OpenOptions::new().write(true).open(bright_path());
-    let file = match file {
-        Ok(f) => f,
-        Err(e) => { log_e(&format!("[DisplayPanel Mode] Could not open brightness file: {}", e)); return; },
-    };
-    let fd = file.as_raw_fd();
-
-    let mut last_val = -1;
-
-    let mut current_val = read_file_int(bright_path()).unwrap_or(hw_min);
-    write_brightness(fd, current_val, &mut last_val, dbg);
-
-    loop {
-        current_val = read_file_int(bright_path()).unwrap_or(current_val);
-
-        match read_file_int(oplus_bright_path()) {
-            Some(oplus_bright) => {
-                if oplus_bright == 0 {
-                    if current_val != BRIGHTNESS_OFF {
-                        current_val = BRIGHTNESS_OFF;
-                        write_brightness(fd, current_val, &mut last_val, dbg);
-                    }
-                } else {
-                    let mode = get_brightness_mode();
-                    let target_val = match mode {
-                        1 => scale_brightness_linear(oplus_bright, hw_min, hw_max, input_min, input_max),
-                        2 => scale_brightness_custom(oplus_bright, hw_min, hw_max, input_min, input_max),
-                        _ => scale_brightness_curved(oplus_bright, hw_min, hw_max, input_min, input_max),
-                    };
-
-                    if current_val != target_val {
-                        let diff = target_val - current_val;
-                        let mut step = diff / 4;
-                        if diff != 0 && step == 0 {
-                            step = if diff > 0 { 1 } else { -1 };
-                        }
-                        
-                        current_val += step;
-                        
-                        if (step > 0 && current_val > target_val) || (step < 0 && current_val < target_val) {
-                            current_val = target_val;
-                        }
-
-                        write_brightness(fd, current_val, &mut last_val, dbg);
-                    }
-                }
-            },
-            None => {
-                if dbg { log_e(&format!("[DisplayPanel Mode] Failed to read from {}", oplus_bright_path())); }
-            }
-        };
-        
-        sleep(Duration::from_millis(33));
-    }
-}
-
-// default mode (os 15+)
-fn run_default_mode() {
-    let dbg = dbg_on();
-    if dbg { log_d("[DisplayAdaptor] Starting in Default Mode..."); }
-    let is_float = is_float_mode();
-    let mode = get_brightness_mode();
-    let is_lux_aod = is_lux_aod_mode();
-    
-    if dbg { 
-        let mode_str = match mode { 1 => "Linear", 2 => "Custom", _ => "Curved" };
-        log_d(&format!("[Default Mode] Mode: {}, Lux AOD: {}", mode_str, is_lux_aod)); 
-    }
-
-    let bright = bright_path();
-
-    let hw_min = get_min_brightness(dbg);
-    let hw_max = get_max_brightness(dbg);
-
-    let mut range = BrightnessRange::init();
-    range.refresh_range();
-    if dbg { log_d(&format!("[Default Mode] IR locked: min={}, max={}", range.min, range.max)); }
-
-    let file = OpenOptions::new().write(true).open(bright);
-    let file = match file {
-        Ok(f) => f,
-        Err(e) => { log_e(&format!("[Default Mode] Could not open brightness file: {}", e)); return; },
-    };
-    let fd = file.as_raw_fd();
-
-    let mut last_val = -1;
-    let mut prev_state = get_screen_state();
-    let mut prev_bright = get_prop_brightness(&range, is_float);
-    if prev_bright == -1 { 
-        if dbg { log_d("[DisplayAdaptor] Initial brightness is 0, using fallback."); }
-        prev_bright = FALLBACK_MIN; 
-    }
-    
-    let initial = match mode {
-        1 => scale_brightness_linear(prev_bright, hw_min, hw_max, range.min, range.max),
-        2 => scale_brightness_custom(prev_bright, hw_min, hw_max, range.min, range.max),
-        _ => scale_brightness_curved(prev_bright, hw_min, hw_max, range.min, range.max),
-    };
-    write_brightness(fd, initial, &mut last_val, dbg);
-
-    let is_ips = is_ips_mode();
-    if dbg { log_d(&format!("[Default Mode] IPS Mode: {}", is_ips)); }
-
-    loop {
-        let cur_state = get_screen_state();
-        let raw_bright = get_prop_brightness(&range, is_float);
-        let cur_bright = if raw_bright == -1 {
-            if dbg { log_d("[DisplayAdaptor] Brightness is 0, ignoring and keeping previous value."); }
-            prev_bright // keep old value
-        } else {
-            raw_bright // use new value
-        };
-
-        let current_mode = get_brightness_mode();
-
-        if cur_bright != prev_bright || cur_state != prev_state {
-            let val_to_write = if cur_state == 2 {
-                if prev_state != 2 { sleep(Duration::from_millis(100)); }
-                match current_mode {
-                    1 => scale_brightness_linear(cur_bright, hw_min, hw_max, range.min, range.max),
-                    2 => scale_brightness_custom(cur_bright, hw_min, hw_max, range.min, range.max),
-                    _ => scale_brightness_curved(cur_bright, hw_min, hw_max, range.min, range.max),
-                }
-            } else if is_ips {
-                // IPS mode (no AOD)
-                if dbg { log_d(&format!("[DisplayAdaptor] IPS Mode: State is {} (OFF), setting brightness 0", cur_state)); }
-                BRIGHTNESS_OFF
-            } else {
-                // AMOLED / Default
-                if cur_state == 0 || cur_state == 1 {
-                     // state is 0 (OFF) or 1 (AOD), treat as OFF
-                    if dbg { log_d(&format!("[DisplayAdaptor] State is {} (OFF), setting brightness 0", cur_state)); }
-                    BRIGHTNESS_OFF
-                } else if cur_state == 3 || cur_state == 4 {
-                    // state is doze (3) or doze_suspend (4)
-                    let is_panoramic = is_panoramic_aod_enabled(dbg);
-                    
-                    if is_lux_aod && is_panoramic {
-                         // Specific case: Lux AOD ON + Panoramic ON
-                         // Check if prop is set
-                         if let Some(target_lux) = get_prop_int(persist_lux_aod_brightness_prop()) {
-                            if target_lux > 0 {
-                                if dbg { log_d(&format!("[DisplayAdaptor] Lux+Panoramic AOD active. Forcing brightness: {}", target_lux)); }
-                                target_lux
-                            } else {
-                                // Prop empty or 0, fallback to standard logic
-                                if dbg { log_d("[DisplayAdaptor] Lux+Panoramic AOD active but prop empty/0. Maintaining last value."); }
-                                last_val
-                            }
-                         } else {
-                             last_val
-                         }
-                    } else if cur_state == 3 && is_lux_aod {
-                        let raw_prop = get_prop("debug.tracing.screen_brightness").unwrap_or_default();
-                        if raw_prop.trim() == "2937.773" {
-                            let lux_val = get_prop_int(persist_lux_aod_brightness_prop()).unwrap_or(1);
-                            if dbg { log_d(&format!("[DisplayAdaptor] Lux AOD: Detected, forcing brightness to {}", lux_val)); }
-                            lux_val
-                        } else {
-                            if dbg { log_d(&format!("[DisplayAdaptor] State is 3 (Doze) & Lux AOD ON: Updating brightness: {}", cur_bright)); }
-                            match current_mode {
-                                1 => scale_brightness_linear(cur_bright, hw_min, hw_max, range.min, range.max),
-                                2 => scale_brightness_custom(cur_bright, hw_min, hw_max, range.min, range.max),
-                                _ => scale_brightness_curved(cur_bright, hw_min, hw_max, range.min, range.max),
-                            }
-                        }
-                    } else if is_panoramic {
-                        if dbg { log_d(&format!("[DisplayAdaptor] State is {} Panoramic AOD is ON, skipping brightness write", cur_state)); }
-                        last_val // don't set to 0
-                    } else {
-                        if dbg { log_d(&format!("[DisplayAdaptor] State is {} Panoramic AOD is OFF, setting brightness 0", cur_state)); }
-                        BRIGHTNESS_OFF // set to 0
-                    }
-                } else if prev_state == 2 {
-                    // transitioned from on (2) to some other state
-                    if is_panoramic_aod_enabled(dbg) {
-                        if dbg { log_d("[DisplayAdaptor] Transitioned from ON with Panoramic AOD, deferring brightness 0"); }
-                        last_val // don't set to 0
-                    } else {
-                        if dbg { log_d("[DisplayAdaptor] Transitioned from ON without Panoramic AOD, setting brightness 0"); }
-                        BRIGHTNESS_OFF // set to 0
-                    }
-                } else {
-                    // other state, keep last value
-                    last_val
-                }
-            };
-
-            if val_to_write != last_val {
-                write_brightness(fd, val_to_write, &mut last_val, dbg);
-            }
-        }
-
-        prev_bright = cur_bright;
-        prev_state = cur_state;
-        sleep(Duration::from_millis(100));
-    }
-}
\ No newline at end of file
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::paths::{control_wake_prop, lease_request_prop, persist_bright_mode_prop};
+use crate::constants::{BRIGHTNESS_OFF, FALLBACK_MIN, INPUT_POLL_MS, OS14_MIN, OS14_MAX, PROP_WAIT_ON_MS, PROP_WAIT_IDLE_MS, SETTINGS_TTL_MS, STATE_POLL_MS};
+use crate::logging::{log_d, log_e};
+use crate::properties::{PropertyStore, get_prop, set_prop, system_props};
+use crate::config::Config;
+use crate::control::{self, Command, Control, Held};
+use crate::lease::{Hold, Leases, Level};
+use crate::env::{Env, FileWait, SystemEnv};
+use crate::utils::{read_file_int, get_max_brightness, get_min_brightness};
+use crate::curve::{Curve, CurveRegistry, ModeWatch, ScalingMode, hw_to_pos, input_to_pos, pos_to_hw, scale};
+use crate::animation::{Ramp, RampConfig};
+use crate::range::BrightnessRange;
+use crate::settings::{PANORAMIC_AOD, SettingsReader};
+use crate::backlight::{Backlight, resolve_backlight};
+use crate::state::{STATE_PROP, ScreenState, StateSourceKind, StateSources};
+use crate::input::{BRIGHTNESS_PROP, InputKind, InputSource};
+use crate::status::{Status, StatusProps};
+use crate::policy::{Action, Inputs, Settings, decide, needs_panoramic};
+use crate::outputs::{Outputs, Source};
+
+// main dispatcher
+pub fn run() {
+    run_with_props(system_props().into());
+}
+
+// dispatcher with an explicit property backend (e.g. a getprop dump off device)
+pub fn run_with_props(store: Arc<dyn PropertyStore + Send + Sync>) {
+    let env = SystemEnv::new(store.clone());
+    let props = store.as_ref();
+    let cfg = Config::load(props);
+    let (control, listener) = open_control(&cfg);
+    std::thread::scope(|s| {
+        if let (Some(c), Some(l)) = (&control, listener) {
+            control::serve(s, l, c, props);
+        }
+        let control = control.as_ref();
+        if cfg.displaypanel {
+            let mut m = PanelMode::start(props, &env, cfg, control);
+            loop { m.pass(); }
+        } else {
+            let mut m = DefaultMode::start(props, &env, cfg, control);
+            loop { m.pass(); }
+        }
+    });
+}
+
+// the control socket is optional, the adaptor runs without it
+fn open_control(cfg: &Config) -> (Option<Control>, Option<std::os::unix::net::UnixListener>) {
+    let Some(name) = cfg.control_socket.as_deref() else { return (None, None); };
+    let control = match Control::new(!cfg.displaypanel) {
+        Ok(c) => c,
+        Err(e) => { log_e(&format!("[Control] eventfd failed: {}", e)); return (None, None); },
+    };
+    match control::bind(name) {
+        Ok(l) => {
+            log_d(&format!("[Control] Listening on {}", name));
+            (Some(control), Some(l))
+        },
+        Err(e) => { log_e(&format!("[Control] Could not bind {}: {}", name, e)); (None, None) },
+    }
+}
+
+// write to the panel and its mirrors, then report it to the env and socket subscribers
+// returns the number of failed writes
+fn emit(env: &dyn Env, control: Option<&Control>, outputs: &mut Outputs, val: i32, src: &Source, now: Instant, dbg: bool) -> u32 {
+    let (res, mirror_failures) = outputs.write(val, src, now, &|d| env.sleep(d), dbg);
+    match res {
+        Ok(true) => {
+            env.wrote(val);
+            if let Some(c) = control { c.wrote(val); }
+            mirror_failures
+        },
+        Ok(false) => mirror_failures,
+        Err(_) => mirror_failures + 1,
+    }
+}
+
+// held level for this pass: Active replaces the output, Ended ramps back from it to `ramp`'s target
+fn apply_hold(hold: Hold, ramp: &mut Ramp, curve: &dyn Curve, hw_min: i32, hw_max: i32, now: Instant) -> Option<i32> {
+    match hold {
+        Hold::Idle => None,
+        Hold::Active(level) => Some(level.clamp(BRIGHTNESS_OFF, hw_max)),
+        Hold::Ended(level) => {
+            let target = ramp.target();
+            ramp.jump(hw_to_pos(curve, level, hw_min, hw_max));
+            ramp.retarget(target, now);
+            Some(pos_to_hw(curve, ramp.sample(now), hw_min, hw_max))
+        },
+    }
+}
+
+// lease level in hw units, input levels go through the active curve
+fn lease_hw(level: Level, curve: &dyn Curve, hw_min: i32, hw_max: i32, input_min: i32, input_max: i32) -> i32 {
+    match level {
+        Level::Hw(v) => v,
+        Level::Input(v) => scale(curve, v, hw_min, hw_max, input_min, input_max),
+    }
+}
+
+// queued socket commands and the lease request property, once per pass
+fn take_requests(props: &dyn PropertyStore, control: Option<&Control>, held: &mut Held, wall: SystemTime) -> bool {
+    if let Some(req) = get_prop(props, lease_request_prop()) {
+        set_prop(props, lease_request_prop(), "");
+        held.leases.request(&req, wall);
+    }
+    let mut reload = false;
+    for cmd in control.map(Control::take).unwrap_or_default() {
+        if cmd == Command::Reload { reload = true; } else { held.apply(cmd, wall); }
+    }
+    reload
+}
+
+fn settings_reader<'a>(env: &'a dyn Env, cfg: &Config) -> SettingsReader<'a> {
+    SettingsReader::new(env.settings(), Duration::from_millis(cfg.settings_ttl_ms.unwrap_or(SETTINGS_TTL_MS)), cfg.debug)
+}
+
+// default mode's take on a read: 0 is skipped as -1, nothing to read falls back to FALLBACK_MIN
+fn framework_level(read: Option<i32>) -> i32 {
+    match read {
+        Some(0) => -1,
+        Some(v) => v,
+        None => FALLBACK_MIN,
+    }
+}
+
+fn curve_label(mode: ScalingMode, curve: &dyn Curve) -> String { format!("{}:{}", mode.id(), curve.name()) }
+
+// every property a default mode pass reads, the wait ignores changes to any other
+// the input range props are only read on start and reload
+fn default_mode_props() -> [&'static str; 5] {
+    [STATE_PROP, BRIGHTNESS_PROP, persist_bright_mode_prop(), lease_request_prop(), control_wake_prop()]
+}
+
+// DisplayPanel mode (os14 and under)
+pub(crate) struct PanelMode<'a> {
+    props: &'a dyn PropertyStore,
+    env: &'a dyn Env,
+    control: Option<&'a Control>,
+    cfg: Config,
+    bl: Backlight,
+    input: InputSource<'a>,
+    bright: PathBuf,
+    outputs: Outputs,
+    hw_min: i32,
+    hw_max: i32,
+    input_min: i32,
+    input_max: i32,
+    curves: CurveRegistry,
+    mode_watch: ModeWatch,
+    ramp: Ramp,
+    watcher: Option<Box<dyn FileWait + 'a>>,
+    held: Held,
+    status_props: StatusProps,
+    last_input: i32,
+    errors: u32,
+    off: bool,
+}
+
+impl<'a> PanelMode<'a> {
+    pub(crate) fn start(props: &'a dyn PropertyStore, env: &'a dyn Env, cfg: Config, control: Option<&'a Control>) -> Self {
+        let dbg = cfg.debug;
+        if dbg { log_d("[DisplayAdaptor] Starting in DisplayPanel Mode..."); }
+
+        let input = InputSource::open(&cfg, props, env.settings_files(), &env.oplus_path(), env.now());
+        if let InputKind::Oplus(oplus_path) = input.kind() && !oplus_path.exists() {
+            if dbg { log_d(&format!("[DisplayPanel Mode] File {} not found, attempting to create it.", oplus_path.display())); }
+            loop {
+                match File::create(oplus_path) {
+                    Ok(_) => {
+                        if dbg { log_d(&format!("[DisplayPanel Mode] Successfully created {}.", oplus_path.display())); }
+                        break;
+                    },
+                    Err(e) => {
+                        log_e(&format!("[DisplayPanel Mode] Failed to create {}, retrying in 1s: {}", oplus_path.display(), e));
+                        env.sleep(Duration::from_secs(1));
+                    }
+                }
+            }
+        }
+        let bl = resolve_backlight(&env.sysfs_root(), &cfg, dbg);
+        let bright = bl.brightness_path();
+        let hw_min = get_min_brightness(props, &cfg, &bl, dbg);
+        let hw_max = get_max_brightness(props, &cfg, &bl, dbg);
+
+        let curves = CurveRegistry::load(&cfg, hw_min, hw_max);
+        let mut mode_watch = ModeWatch::new(cfg.mode);
+
+        let input_min = cfg.panel_min.unwrap_or(OS14_MIN);
+        let input_max = cfg.panel_max.unwrap_or(OS14_MAX);
+        if dbg { log_d(&format!("[DisplayPanel Mode] Scaling range: {}-{} -> {}-{}", input_min, input_max, hw_min, hw_max)); }
+
+        let mut outputs = Outputs::open(&cfg, &bl, hw_min, hw_max, env.now());
+        let current_val = read_file_int(&bright).map_or(hw_min, |v| outputs.unscale(v));
+        let start_mode = cfg.live_mode(props, &mut mode_watch);
+        let start_curve = curves.get(start_mode);
+        let src = Source { curve: start_curve, mode: start_mode, hw_min, hw_max };
+        let errors = emit(env, control, &mut outputs, current_val, &src, env.now(), dbg);
+        let ramp = Ramp::new(RampConfig::from_config(&cfg), hw_to_pos(start_curve, current_val, hw_min, hw_max), env.now());
+
+        // wake on writes to the oplus file, socket commands and lease requests, fall back to frame polling if inotify is unavailable
+        // other sources are polled
+        let watcher = match input.kind() {
+            InputKind::Oplus(oplus_path) => match env.watch(oplus_path, control.map(Control::wake_fd), &[lease_request_prop()]) {
+                Ok(w) => Some(w),
+                Err(e) => { log_e(&format!("[DisplayPanel Mode] inotify unavailable, polling instead: {}", e)); None },
+            },
+            _ => None,
+        };
+
+        Self {
+            props, env, control, cfg, bl, input, bright, outputs, hw_min, hw_max, input_min, input_max,
+            curves, mode_watch, ramp, watcher, held: Held::new(Leases::load(env.lease_store(), env.wall_time())),
+            status_props: StatusProps::new(), last_input: -1, errors, off: current_val == BRIGHTNESS_OFF,
+        }
+    }
+
+    // re-read config on a socket reload, the backlight and mode stay as started
+    fn reload(&mut self, now: Instant) {
+        let cfg = self.env.load_config(self.props);
+        if !cfg.displaypanel { log_e("[Control] DisplayPanel mode turned off, restart the service to switch modes"); }
+        let dbg = cfg.debug;
+        self.hw_min = get_min_brightness(self.props, &cfg, &self.bl, dbg);
+        self.hw_max = get_max_brightness(self.props, &cfg, &self.bl, dbg);
+        self.curves = CurveRegistry::load(&cfg, self.hw_min, self.hw_max);
+        self.mode_watch = ModeWatch::new(cfg.mode);
+        self.input_min = cfg.panel_min.unwrap_or(OS14_MIN);
+        self.input_max = cfg.panel_max.unwrap_or(OS14_MAX);
+        if cfg.input_source != self.cfg.input_source { log_e("[Control] Brightness source changed, restart the service to switch it"); }
+        self.ramp = Ramp::new(RampConfig::from_config(&cfg), self.ramp.sample(now), now);
+        self.outputs.reconfigure(&cfg, &self.bl, self.hw_min, self.hw_max, now);
+        self.cfg = cfg;
+        log_d("[Control] Config reloaded");
+    }
+
+    // one read/write pass, returns how long to wait at most for the next one
+    pub(crate) fn step(&mut self) -> Duration {
+        let now = self.env.now();
+        let wall = self.env.wall_time();
+        if take_requests(self.props, self.control, &mut self.held, wall) { self.reload(now); }
+        let dbg = self.cfg.debug;
+        let mode = self.held.mode.unwrap_or_else(|| self.cfg.live_mode(self.props, &mut self.mode_watch));
+        let curve = self.curves.get(mode);
+
+        match self.input.read(self.props, self.input_min, self.input_max, now) {
+            Some(0) => {
+                self.off = true;
+                self.ramp.jump(0.0);
+            },
+            Some(oplus_bright) => {
+                self.last_input = oplus_bright;
+                let target = input_to_pos(oplus_bright, self.input_min, self.input_max);
+                if self.off {
+                    // screen on, no fade in
+                    self.off = false;
+                    self.ramp.jump(target);
+                } else {
+                    // pick up levels written behind our back before ramping from them
+                    if !self.ramp.is_active()
+                        && let Some(cur) = read_file_int(&self.bright).and_then(|v| self.outputs.foreign(v))
+                        && cur != BRIGHTNESS_OFF {
+                        self.ramp.jump(hw_to_pos(curve, cur, self.hw_min, self.hw_max));
+                    }
+                    self.ramp.retarget(target, now);
+                }
+            },
+            None => {
+                if dbg { log_e(&format!("[DisplayPanel Mode] Failed to read from {}", self.input.kind().describe())); }
+            }
+        };
+
+        let (hw_min, hw_max, input_min, input_max) = (self.hw_min, self.hw_max, self.input_min, self.input_max);
+        let hold = self.held.leases.poll(wall, |l| lease_hw(l, curve, hw_min, hw_max, input_min, input_max));
+        let held = if self.off { None } else { apply_hold(hold, &mut self.ramp, curve, hw_min, hw_max, now) };
+        let val = match held {
+            Some(v) => v,
+            None if self.off => BRIGHTNESS_OFF,
+            None => pos_to_hw(curve, self.ramp.sample(now), self.hw_min, self.hw_max),
+        };
+        let src = Source { curve, mode, hw_min, hw_max };
+        self.errors += emit(self.env, self.control, &mut self.outputs, val, &src, now, dbg);
+
+        let status = Status {
+            mode: "displaypanel",
+            curve: curve_label(mode, curve),
+            hw_min: self.hw_min,
+            hw_max: self.hw_max,
+            input_min: self.input_min,
+            input_max: self.input_max,
+            input: self.last_input,
+            last: self.outputs.last(),
+            state: None,
+            override_level: self.held.leases.level(),
+            panoramic: false,
+            errors: self.errors,
+        };
+        let publish_in = self.status_props.publish(self.props, &status, now);
+        if let Some(c) = self.control { c.publish(status); }
+
+        // tick the ramp timer mid transition, otherwise wake for file changes, lease requests, expiry, write retries and held back status
+        let timeout = if self.ramp.is_active() { self.ramp.frame() } else { Duration::from_millis(PROP_WAIT_ON_MS) };
+        // the source is all there is to tell the screen came back, off it's only read at the 1s timeout
+        let timeout = if self.input.polled() && !self.off { timeout.min(Duration::from_millis(INPUT_POLL_MS)) } else { timeout };
+        [self.held.leases.remaining(wall), self.outputs.retry_in(now), publish_in].into_iter().flatten().fold(timeout, Duration::min)
+    }
+
+    // one step plus the wait after it
+    pub(crate) fn pass(&mut self) {
+        let timeout = self.step();
+        let frame = self.ramp.frame();
+        match &self.watcher {
+            Some(w) => {
+                if let Err(e) = w.wait(Some(timeout)) {
+                    log_e(&format!("[DisplayPanel Mode] inotify wait failed: {}", e));
+                    self.env.sleep(frame);
+                }
+            },
+            // a polled source waits out the step, a missing inotify ticks at frame rate
+            None if self.input.polled() => self.env.sleep(timeout),
+            None => self.env.sleep(frame),
+        }
+    }
+}
+
+// default mode (os 15+)
+pub(crate) struct DefaultMode<'a> {
+    props: &'a dyn PropertyStore,
+    env: &'a dyn Env,
+    control: Option<&'a Control>,
+    cfg: Config,
+    bl: Backlight,
+    outputs: Outputs,
+    hw_min: i32,
+    hw_max: i32,
+    curves: CurveRegistry,
+    mode_watch: ModeWatch,
+    range: BrightnessRange,
+    input: InputSource<'a>,
+    ramp: Ramp,
+    policy: Settings,
+    settings: SettingsReader<'a>,
+    held: Held,
+    status_props: StatusProps,
+    states: StateSources,
+    state_source: Option<StateSourceKind>, // source of the last reading, None while none answers
+    prev_state: Option<ScreenState>, // last known state, never guessed
+    prev_bright: i32,
+    panoramic: bool, // last panoramic AOD lookup
+    errors: u32,
+    force: bool, // re-evaluate on the next pass even without a property change
+}
+
+impl<'a> DefaultMode<'a> {
+    pub(crate) fn start(props: &'a dyn PropertyStore, env: &'a dyn Env, cfg: Config, control: Option<&'a Control>) -> Self {
+        let dbg = cfg.debug;
+        if dbg { log_d("[DisplayAdaptor] Starting in Default Mode..."); }
+
+        if dbg {
+            log_d(&format!("[Default Mode] Mode: {:?}, Lux AOD: {}", cfg.mode, cfg.lux_aod));
+        }
+
+        let bl = resolve_backlight(&env.sysfs_root(), &cfg, dbg);
+
+        let hw_min = get_min_brightness(props, &cfg, &bl, dbg);
+        let hw_max = get_max_brightness(props, &cfg, &bl, dbg);
+        let curves = CurveRegistry::load(&cfg, hw_min, hw_max);
+        let mut mode_watch = ModeWatch::new(cfg.mode);
+
+        let mut range = BrightnessRange::init(&cfg);
+        range.refresh_range(props);
+        if dbg { log_d(&format!("[Default Mode] IR locked: min={}, max={}", range.min, range.max)); }
+
+        let mut outputs = Outputs::open(&cfg, &bl, hw_min, hw_max, env.now());
+        let states = StateSources::open(&cfg, &env.sysfs_root(), &bl);
+        let reading = states.read(props);
+        if reading.is_none() { log_e("[State] No screen state source answered, holding brightness until one does"); }
+        let prev_state = reading.map(|r| r.state);
+        let mut input = InputSource::open(&cfg, props, env.settings_files(), &env.oplus_path(), env.now());
+        let mut prev_bright = framework_level(input.read(props, range.min, range.max, env.now()));
+        if prev_bright == -1 {
+            if dbg { log_d("[DisplayAdaptor] Initial brightness is 0, using fallback."); }
+            prev_bright = FALLBACK_MIN;
+        }
+
+        let initial_pos = input_to_pos(prev_bright, range.min, range.max);
+        let ramp = Ramp::new(RampConfig::from_config(&cfg), initial_pos, env.now());
+        let start_mode = cfg.live_mode(props, &mut mode_watch);
+        let src = Source { curve: curves.get(start_mode), mode: start_mode, hw_min, hw_max };
+        // only a screen known to be on gets the level, other states go through the policy on the first pass
+        let on = reading.is_some_and(|r| r.state.is_display_on());
+        let initial = pos_to_hw(src.curve, initial_pos, hw_min, hw_max);
+        let errors = if on { emit(env, control, &mut outputs, initial, &src, env.now(), dbg) } else { 0 };
+
+        let policy = Settings::from_config(&cfg);
+        if dbg { log_d(&format!("[Default Mode] IPS Mode: {}", policy.ips)); }
+        let settings = settings_reader(env, &cfg);
+
+        Self {
+            props, env, control, cfg, bl, outputs, hw_min, hw_max, curves, mode_watch, range, input, ramp, policy, settings,
+            held: Held::new(Leases::load(env.lease_store(), env.wall_time())), status_props: StatusProps::new(),
+            states, state_source: reading.map(|r| r.source), prev_state, prev_bright, panoramic: false, errors,
+            force: reading.is_some() && !on,
+        }
+    }
+
+    // re-read config on a socket reload, the backlight and mode stay as started
+    fn reload(&mut self, now: Instant) {
+        let cfg = self.env.load_config(self.props);
+        if cfg.displaypanel { log_e("[Control] DisplayPanel mode turned on, restart the service to switch modes"); }
+        let dbg = cfg.debug;
+        self.hw_min = get_min_brightness(self.props, &cfg, &self.bl, dbg);
+        self.hw_max = get_max_brightness(self.props, &cfg, &self.bl, dbg);
+        self.curves = CurveRegistry::load(&cfg, self.hw_min, self.hw_max);
+        self.mode_watch = ModeWatch::new(cfg.mode);
+        self.range = BrightnessRange::init(&cfg);
+        self.range.refresh_range(self.props);
+        self.policy = Settings::from_config(&cfg);
+        self.settings = settings_reader(self.env, &cfg);
+        self.input = InputSource::open(&cfg, self.props, self.env.settings_files(), &self.env.oplus_path(), now);
+        self.ramp = Ramp::new(RampConfig::from_config(&cfg), self.ramp.sample(now), now);
+        self.outputs.reconfigure(&cfg, &self.bl, self.hw_min, self.hw_max, now);
+        self.cfg = cfg;
+        self.force = true;
+        log_d("[Control] Config reloaded");
+    }
+
+    // one read/decide/write pass, returns how long to wait for the next property change
+    pub(crate) fn step(&mut self) -> Duration {
+        let now = self.env.now();
+        let wall = self.env.wall_time();
+        if take_requests(self.props, self.control, &mut self.held, wall) { self.reload(now); }
+        let dbg = self.cfg.debug;
+        let props = self.props;
+        let (hw_min, hw_max) = (self.hw_min, self.hw_max);
+        let (prev_state, prev_bright) = (self.prev_state, self.prev_bright);
+
+        // a source that stops answering leaves the last known state in place instead of assuming ON
+        let reading = self.states.read(props);
+        let source = reading.map(|r| r.source);
+        if source != self.state_source {
+            match source {
+                Some(s) => log_d(&format!("[State] Screen state now from {}", s.name())),
+                None => log_e(&format!("[State] No screen state source answered, keeping {}", prev_state.map_or("none", ScreenState::name))),
+            }
+            self.state_source = source;
+        }
+        let cur_state = reading.map(|r| r.state).or(prev_state);
+        let on = cur_state.is_some_and(ScreenState::is_display_on);
+        let raw_bright = framework_level(self.input.read(props, self.range.min, self.range.max, now));
+        let cur_bright = if raw_bright == -1 {
+            if dbg { log_d("[DisplayAdaptor] Brightness is 0, ignoring and keeping previous value."); }
+            prev_bright // keep old value
+        } else {
+            raw_bright // use new value
+        };
+
+        let mode = self.held.mode.unwrap_or_else(|| self.cfg.live_mode(props, &mut self.mode_watch));
+        let curve = self.curves.get(mode);
+
+        let mut val = None;
+        let was_on = prev_state.is_some_and(ScreenState::is_display_on);
+        // nothing is written until some source has reported a state
+        if let Some(state) = cur_state && (self.force || cur_bright != prev_bright || cur_state != prev_state) {
+            if !on { self.ramp.cancel(now); }
+            // settings only change while the screen is on, re-read them once it leaves ON
+            if was_on && !on { self.settings.invalidate(); }
+            val = Some(if on {
+                let target = input_to_pos(cur_bright, self.range.min, self.range.max);
+                if !was_on {
+                    // screen on, no fade in
+                    self.env.sleep(Duration::from_millis(100));
+                    self.ramp.jump(target);
+                } else {
+                    self.ramp.retarget(target, now);
+                }
+                pos_to_hw(curve, self.ramp.sample(now), hw_min, hw_max)
+            } else {
+                // doze / AOD / off, decided by the state policy
+                let prev = prev_state.unwrap_or(ScreenState::Unknown);
+                let inputs = Inputs {
+                    panoramic: needs_panoramic(prev, state, &self.policy)
+                        && self.settings.get_secure(PANORAMIC_AOD, now).as_deref() == Some("1"),
+                    lux_sentinel: get_prop(props, BRIGHTNESS_PROP).is_some_and(|v| v.trim() == "2937.773"),
+                };
+                self.panoramic = inputs.panoramic;
+                let decision = decide(prev, state, &inputs, &self.policy);
+                if dbg { log_d(&format!("[DisplayAdaptor] State {} -> {}: {:?} ({})", prev.name(), state.name(), decision.action, decision.reason)); }
+                match decision.action {
+                    Action::Off => BRIGHTNESS_OFF,
+                    Action::Keep => self.outputs.last(),
+                    Action::Fixed(v) => v,
+                    Action::Scaled => scale(curve, cur_bright, hw_min, hw_max, self.range.min, self.range.max),
+                }
+            });
+        } else if self.ramp.is_active() {
+            // mid ramp, advance a frame
+            val = Some(pos_to_hw(curve, self.ramp.sample(now), hw_min, hw_max));
+        }
+
+        // override leases only hold while the screen is on
+        let (input_min, input_max) = (self.range.min, self.range.max);
+        let hold = self.held.leases.poll(wall, |l| lease_hw(l, curve, hw_min, hw_max, input_min, input_max));
+        if on && let Some(v) = apply_hold(hold, &mut self.ramp, curve, hw_min, hw_max, now) {
+            val = Some(v);
+        }
+        // a level that didn't make it to a node is resent once its writer is ready again
+        if let Some(v) = val.or(self.outputs.pending()) {
+            let src = Source { curve, mode, hw_min, hw_max };
+            self.errors += emit(self.env, self.control, &mut self.outputs, v, &src, now, dbg);
+        }
+
+        self.prev_bright = cur_bright;
+        self.prev_state = cur_state;
+        self.force = false;
+
+        let status = Status {
+            mode: "default",
+            curve: curve_label(mode, curve),
+            hw_min,
+            hw_max,
+            input_min: self.range.min,
+            input_max: self.range.max,
+            input: cur_bright,
+            last: self.outputs.last(),
+            state: cur_state,
+            override_level: self.held.leases.level(),
+            panoramic: self.panoramic,
+            errors: self.errors,
+        };
+        // publishing bumps the property serial, so the next wait returns once and then settles on the props it reads
+        let publish_in = self.status_props.publish(props, &status, now);
+        if let Some(c) = self.control { c.publish(status); }
+
+        // ramps tick at frame rate and screen off idles on a long timeout
+        let timeout = if self.ramp.is_active() {
+            self.ramp.frame()
+        } else if on {
+            Duration::from_millis(PROP_WAIT_ON_MS)
+        } else {
+            Duration::from_millis(PROP_WAIT_IDLE_MS)
+        };
+        // sysfs state nodes don't bump the property serial, poll them while the prop isn't answering
+        let timeout = if source.map_or(self.states.has_nodes(), |s| s != StateSourceKind::Prop) {
+            timeout.min(Duration::from_millis(STATE_POLL_MS))
+        } else {
+            timeout
+        };
+        // polled brightness only matters with the screen on, state changes wake the loop anyway
+        let timeout = if self.input.polled() && on { timeout.min(Duration::from_millis(INPUT_POLL_MS)) } else { timeout };
+        [self.held.leases.remaining(wall), self.outputs.retry_in(now), publish_in].into_iter().flatten().fold(timeout, Duration::min)
+    }
+
+    // one step, then block until a property it reads changes or the step's timeout passes
+    pub(crate) fn pass(&mut self) {
+        // snapshot before reading so changes during this pass still wake the next wait
+        let serial = self.props.change_serial();
+        let timeout = self.step();
+        self.props.wait_change(serial, &default_mode_props(), timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::control::Command;
+    use crate::harness::Harness;
+    use crate::properties::PropertyStore;
+    use crate::paths::{
+        lease_request_prop,
+        persist_bright_mode_prop, persist_min, persist_max, persist_oplus_min, persist_oplus_max, is_oplus_panel_prop,
+        persist_ramp_duration_prop, persist_ramp_easing_prop, persist_ramp_frame_prop,
+        persist_lux_aod_prop, persist_lux_aod_brightness_prop, persist_aod_policy_prop, persist_mirrors_prop,
+        persist_write_scale_prop, persist_input_source_prop, sys_prop_min, sys_prop_max,
+    };
+
+    const STATE: &str = "debug.tracing.screen_state";
+    const BRIGHT: &str = "debug.tracing.screen_brightness";
+
+    // hw 1..2047 behind a linear curve with the same input range, so input levels are written as is
+    fn device(state: &str, bright: &str, ramp_ms: &str) -> Harness {
+        let h = Harness::new(1, 2047, 0);
+        h.prop(persist_bright_mode_prop(), "1")
+            .prop(persist_min(), "1").prop(persist_max(), "2047")
+            .prop(persist_ramp_duration_prop(), ramp_ms)
+            .prop(persist_ramp_easing_prop(), "linear")
+            .prop(persist_ramp_frame_prop(), "20")
+            .prop(STATE, state).prop(BRIGHT, bright);
+        h
+    }
+
+    #[test]
+    fn screen_off_and_back_on() {
+        let h = device("2", "1000", "0");
+        h.at(500, STATE, "1").at(500, BRIGHT, "0").at(1000, STATE, "2").at(1000, BRIGHT, "1000");
+        h.run_default(3000);
+        // off right away, back on after the 100ms settle delay without a fade
+        assert_eq!(h.writes(), vec![(0, 1000), (500, 0), (1100, 1000)]);
+        // status properties catch up once the rate limit allows
+        for (key, val) in [("state", "2"), ("last", "1000"), ("input", "1000"), ("errors", "0")] {
+            assert_eq!(h.get(&format!("sys.rianixia.display.{}", key)).as_deref(), Some(val), "{}", key);
+        }
+    }
+
+    #[test]
+    fn brightness_change_ramps_at_frame_rate() {
+        let h = device("2", "1", "100");
+        h.at(1000, BRIGHT, "2047");
+        assert_eq!(h.run_default(2000), vec![1, 410, 819, 1229, 1638, 2047]);
+        assert_eq!(h.writes().last(), Some(&(1100, 2047)));
+    }
+
+    #[test]
+    fn float_brightness_detected_without_isfloat() {
+        let h = device("2", "0.5", "0");
+        h.at(500, BRIGHT, "1.0").at(1000, BRIGHT, "0.0");
+        // 0.0 is skipped like an int 0
+        assert_eq!(h.run_default(2000), vec![1024, 2047]);
+    }
+
+    #[test]
+    fn setting_source_is_only_polled_with_the_screen_on() {
+        let h = device("2", "0", "0");
+        h.prop(persist_input_source_prop(), "setting:system/screen_brightness_float");
+        h.at(5000, STATE, "1");
+        // every 500ms for the 5s on, then nothing more over 25s off besides the odd wakeup
+        h.run_default(30_000);
+        assert!((10..=13).contains(&h.system_reads()), "{} reads", h.system_reads());
+    }
+
+    #[test]
+    fn screen_off_cancels_ramp() {
+        let h = device("2", "1", "100");
+        h.at(1000, BRIGHT, "2047").at(1050, STATE, "1");
+        assert_eq!(h.run_default(2000), vec![1, 410, 819, 0]);
+    }
+
+    #[test]
+    fn panoramic_aod_keeps_level_until_off() {
+        let h = device("2", "1000", "0");
+        h.set_panoramic(true);
+        h.at(500, STATE, "3").at(1000, STATE, "4").at(1500, STATE, "1");
+        assert_eq!(h.run_default(2000), vec![1000, 0]);
+    }
+
+    #[test]
+    fn aod_without_panoramic_turns_off() {
+        let h = device("2", "1000", "0");
+        h.at(500, STATE, "3").at(1000, STATE, "2");
+        assert_eq!(h.run_default(2000), vec![1000, 0, 1000]);
+    }
+
+    #[test]
+    fn vr_and_on_suspend_stay_scaled_with_leases() {
+        let h = device("2", "1000", "0");
+        h.at(500, STATE, "5").at(1000, BRIGHT, "1500")
+            .at_control(1200, Command::Override(Some((2047, None))))
+            .at(1500, STATE, "6").at(2000, STATE, "2");
+        // no blanking, no settle delay, the override keeps holding across the states
+        assert_eq!(h.run_default(2500), vec![1000, 1500, 2047]);
+        assert_eq!(h.writes()[1..], [(1000, 1500), (1200, 2047)]);
+    }
+
+    #[test]
+    fn unlisted_props_do_not_end_the_wait() {
+        let quiet = device("2", "1000", "0");
+        quiet.run_default(5000);
+        let h = device("2", "1000", "0");
+        for t in (100..5000).step_by(100) {
+            h.at(t, sys_prop_min(), "1").at(t, sys_prop_max(), &t.to_string()).at(t, persist_max(), "4095").at(t, "sys.other", &t.to_string());
+        }
+        assert_eq!(h.run_default(5000), vec![1000]);
+        assert_eq!(h.passes(), quiet.passes());
+        // a prop the pass reads does
+        let woken = device("2", "1000", "0");
+        woken.at(2500, persist_bright_mode_prop(), "1");
+        woken.run_default(5000);
+        assert_eq!(woken.passes(), quiet.passes() + 1);
+    }
+
+    #[test]
+    fn missing_state_prop_keeps_last_state() {
+        let h = device("2", "1000", "0");
+        h.at(500, STATE, "3").at(1000, STATE, "").at(1000, BRIGHT, "1500");
+        // doze stays dark once the prop is gone instead of assuming ON
+        assert_eq!(h.run_default(2000), vec![1000, 0]);
+        assert_eq!(h.get("sys.rianixia.display.state").as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn bl_power_drives_state_without_the_prop() {
+        let h = device("", "1000", "0");
+        let bl_power = h.backlight_dir().join("bl_power");
+        std::fs::write(&bl_power, "0").unwrap();
+        h.at_file(500, &bl_power, "4").at_file(1000, &bl_power, "0").at(1500, BRIGHT, "1200");
+        h.run_default(2000);
+        assert_eq!(h.writes(), vec![(0, 1000), (500, 0), (1100, 1000), (1500, 1200)]);
+    }
+
+    #[test]
+    fn nothing_decided_until_a_state_is_known() {
+        let h = device("", "1000", "0");
+        h.prop("persist.sys.rianixia.display.state_sources", "prop");
+        h.at(500, BRIGHT, "1500").at(1000, STATE, "2").at(1000, BRIGHT, "1200");
+        assert_eq!(h.writes(), vec![]);
+        assert_eq!(h.run_default(2000), vec![1200]);
+        assert_eq!(h.writes()[0], (1100, 1200));
+    }
+
+    #[test]
+    fn starting_with_the_screen_off_writes_no_level() {
+        let h = device("1", "1000", "0");
+        h.at(1000, STATE, "2");
+        // the off state's policy on the first pass, the level once it turns on
+        assert_eq!(h.run_default(2000), vec![0, 1000]);
+        assert_eq!(h.writes()[1], (1100, 1000));
+    }
+
+    #[test]
+    fn panoramic_setting_read_once_per_screen_transition() {
+        let h = device("2", "1000", "0");
+        h.set_panoramic(true);
+        h.at(500, STATE, "3").at(1000, STATE, "4").at(1500, STATE, "2").at(2000, STATE, "3");
+        assert_eq!(h.run_default(3000), vec![1000]);
+        // doze -> doze suspend reuses it, leaving ON reads it again
+        assert_eq!(h.settings_reads(), 2);
+    }
+
+    #[test]
+    fn aod_policy_table_overrides_doze_suspend() {
+        let h = device("2", "1000", "0");
+        h.prop(persist_aod_policy_prop(), "4:fixed:10");
+        h.set_panoramic(true);
+        h.at(500, STATE, "3").at(1000, STATE, "4").at(1500, STATE, "1");
+        assert_eq!(h.run_default(2000), vec![1000, 10, 0]);
+    }
+
+    #[test]
+    fn lux_aod_marker_then_follows_brightness() {
+        let h = device("2", "1000", "0");
+        h.prop(persist_lux_aod_prop(), "true").prop(persist_lux_aod_brightness_prop(), "40");
+        h.at(500, STATE, "3").at(500, BRIGHT, "2937.773")
+            .at(1000, BRIGHT, "300")
+            .at(1500, STATE, "2").at(1500, BRIGHT, "800");
+        assert_eq!(h.run_default(2000), vec![1000, 40, 300, 800]);
+    }
+
+    #[test]
+    fn lux_with_panoramic_aod_forces_brightness() {
+        let h = device("2", "1000", "0");
+        h.prop(persist_lux_aod_prop(), "true").prop(persist_lux_aod_brightness_prop(), "25");
+        h.set_panoramic(true);
+        h.at(500, STATE, "3").at(1000, STATE, "4").at(1000, BRIGHT, "300");
+        assert_eq!(h.run_default(2000), vec![1000, 25]);
+    }
+
+    #[test]
+    fn socket_override_holds_then_ramps_back() {
+        let h = device("2", "1000", "100");
+        h.at_control(500, Command::Override(Some((2047, Some(Duration::from_millis(300))))));
+        // framework changes while held are tracked and ramped to on release
+        h.at(600, BRIGHT, "1").at(2000, STATE, "1");
+        assert_eq!(h.run_default(3000), vec![1000, 2047, 1638, 1229, 819, 410, 1, 0]);
+        assert_eq!(h.writes()[1..3], [(500, 2047), (820, 1638)]);
+    }
+
+    #[test]
+    fn socket_override_waits_for_screen_on() {
+        let h = device("1", "0", "0");
+        h.at_control(500, Command::Override(Some((2047, None))))
+            .at(1000, STATE, "2").at(1000, BRIGHT, "1000")
+            .at_control(1500, Command::Override(None));
+        // off stays off, held once on, released back to the framework level
+        assert_eq!(h.run_default(2000), vec![0, 2047, 1000]);
+        assert_eq!(h.writes()[1..], [(1100, 2047), (1500, 1000)]);
+    }
+
+    #[test]
+    fn property_lease_expires_and_survives_restart() {
+        let h = device("2", "1000", "100");
+        h.at(500, lease_request_prop(), "qr:hw:2047:1000:5").at(700, lease_request_prop(), "factory:input:1:2000");
+        // the higher priority lease holds, the request prop is consumed
+        assert_eq!(h.run_default(1000), vec![1000, 2047]);
+        assert_eq!(h.get(lease_request_prop()), None);
+
+        // a restart picks the live leases back up, expiry hands over to the next lease, the last one ramps back
+        h.run_default(3000);
+        assert_eq!(h.writes()[2..], [(1000, 1000), (1000, 2047), (1500, 1), (2720, 201), (2740, 401), (2760, 600), (2780, 800), (2800, 1000)]);
+    }
+
+    #[test]
+    fn mirrors_follow_in_their_own_range_and_format() {
+        let h = device("2", "1", "0");
+        let mirror = h.root().join("panel1");
+        std::fs::write(&mirror, "").unwrap();
+        let missing = h.root().join("gone/brightness");
+        h.prop(persist_mirrors_prop(), &format!("{};range=1-255;format=newline, {}", mirror.display(), missing.display()));
+        h.at(500, BRIGHT, "1024");
+        // the missing mirror keeps retrying on its own, the panel and the other mirror carry on
+        assert_eq!(h.run_default(1000), vec![1, 1024]);
+        assert_eq!(std::fs::read(&mirror).unwrap(), b"128\n");
+        assert_eq!(h.get("sys.rianixia.display.errors").as_deref(), Some("0"));
+    }
+
+    fn panel(level: i32) -> Harness {
+        let h = Harness::new(1, 2047, level);
+        h.prop(is_oplus_panel_prop(), "true")
+            .prop(persist_bright_mode_prop(), "1")
+            .prop(persist_oplus_min(), "1").prop(persist_oplus_max(), "2047")
+            .prop(persist_ramp_duration_prop(), "100")
+            .prop(persist_ramp_easing_prop(), "linear")
+            .prop(persist_ramp_frame_prop(), "20");
+        h
+    }
+
+    #[test]
+    fn displaypanel_ramps_then_snaps_off_and_on() {
+        let h = panel(1);
+        h.at_oplus(1000, 2047).at_oplus(2000, 0).at_oplus(3000, 1000);
+        assert_eq!(h.run_panel(4000), vec![1, 410, 819, 1229, 1638, 2047, 0, 1000]);
+        assert_eq!(h.writes()[5..], [(1100, 2047), (2000, 0), (3000, 1000)]);
+    }
+
+    #[test]
+    fn displaypanel_ramps_from_external_level() {
+        let h = panel(1);
+        // someone else sets the node, the next ramp starts from there
+        h.at_node(500, 1024).at_oplus(1000, 2047);
+        assert_eq!(h.run_panel(2000), vec![1, 1024, 1229, 1433, 1638, 1842, 2047]);
+    }
+
+    #[test]
+    fn displaypanel_scaled_node_is_not_an_external_level() {
+        let h = panel(4);
+        h.prop(persist_write_scale_prop(), "*4");
+        // the node holds our levels times 4, only a real outside write is picked up
+        h.at_oplus(500, 1000).at_oplus(1000, 1000).at_oplus(1500, 1000).at_node(2000, 4096).at_oplus(2000, 2047);
+        assert_eq!(h.run_panel(3000), vec![1, 201, 401, 600, 800, 1000, 1024, 1229, 1433, 1638, 1842, 2047]);
+        assert_eq!(std::fs::read(h.backlight_dir().join("brightness")).unwrap(), b"8188\0");
+    }
+
+    #[test]
+    fn displaypanel_picks_up_lease_requests_right_away() {
+        let h = panel(1000);
+        h.at_oplus(100, 1000).at(700, lease_request_prop(), "qr:hw:2047:1000:5");
+        h.run_panel(1500);
+        // applied when requested, not at the next 1s timeout
+        assert_eq!(h.writes().iter().find(|(_, v)| *v == 2047), Some(&(700, 2047)));
+        assert_eq!(h.get(lease_request_prop()), None);
+    }
+}
@@ -0,0 +1,197 @@
+// the panel backlight node plus optional mirrors: panel specific nodes or a secondary display that want the same level
+// every mirror has its own hw range, curve and write format, and its own writer so a failing node doesn't hold up the rest
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use crate::backlight::Backlight;
+use crate::config::Config;
+use crate::constants::BRIGHTNESS_OFF;
+use crate::curve::{Curve, CurveRegistry, ScalingMode, hw_to_pos, pos_to_hw};
+use crate::logging::{log_d, log_e};
+use crate::scaling::load_custom_curve;
+use crate::writer::{BacklightWriter, Encoding, Readback, Scale, WriteFormat};
+
+// one `backlight.mirrors` entry, unset options follow the panel backlight
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MirrorSpec {
+    pub(crate) path: PathBuf,
+    pub(crate) range: Option<(i32, i32)>,
+    pub(crate) mode: Option<ScalingMode>,
+    pub(crate) encoding: Option<Encoding>,
+    pub(crate) scale: Option<Scale>,
+}
+
+impl MirrorSpec {
+    // the node written to, a directory means its brightness node
+    pub(crate) fn node(&self) -> PathBuf {
+        if self.path.is_dir() { self.path.join("brightness") } else { self.path.clone() }
+    }
+}
+
+// the entries of a `backlight.mirrors` list
+pub(crate) fn mirror_entries(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|e| !e.is_empty())
+}
+
+// "<path>[;range=MIN-MAX][;mode=N][;format=F][;scale=S]"
+pub(crate) fn parse_mirror(entry: &str) -> Result<MirrorSpec, String> {
+    let mut parts = entry.split(';').map(str::trim);
+    let path = parts.next().filter(|p| p.starts_with('/')).ok_or_else(|| format!("mirror '{}' needs an absolute path", entry))?;
+    let mut spec = MirrorSpec { path: PathBuf::from(path), range: None, mode: None, encoding: None, scale: None };
+    for opt in parts {
+        let (key, val) = opt.split_once('=').map(|(k, v)| (k.trim(), v.trim())).ok_or_else(|| format!("malformed mirror option '{}'", opt))?;
+        match key {
+            "range" => {
+                let range = val.split_once('-')
+                    .and_then(|(lo, hi)| Some((lo.trim().parse::<i32>().ok()?, hi.trim().parse::<i32>().ok()?)))
+                    .filter(|(lo, hi)| *lo >= 0 && lo < hi)
+                    .ok_or_else(|| format!("invalid mirror range '{}'", val))?;
+                spec.range = Some(range);
+            },
+            "mode" => spec.mode = Some(ScalingMode::parse(val)?),
+            "format" => spec.encoding = Some(Encoding::parse(val).ok_or_else(|| format!("unknown mirror format '{}'", val))?),
+            "scale" => spec.scale = Some(Scale::parse(val).ok_or_else(|| format!("invalid mirror scale '{}'", val))?),
+            _ => return Err(format!("unknown mirror option '{}'", key)),
+        }
+    }
+    Ok(spec)
+}
+
+// the primary level being mirrored: the curve and range it was mapped with
+pub(crate) struct Source<'c> {
+    pub(crate) curve: &'c dyn Curve,
+    pub(crate) mode: ScalingMode,
+    pub(crate) hw_min: i32,
+    pub(crate) hw_max: i32,
+}
+
+struct Mirror {
+    writer: BacklightWriter,
+    hw_min: i32,
+    hw_max: i32,
+    mode: Option<ScalingMode>,
+    curves: CurveRegistry,
+}
+
+impl Mirror {
+    // same perceptual position through the mirror's own curve and range, off stays off
+    fn level(&self, val: i32, src: &Source) -> i32 {
+        if val == BRIGHTNESS_OFF { return BRIGHTNESS_OFF; }
+        let pos = hw_to_pos(src.curve, val, src.hw_min, src.hw_max);
+        pos_to_hw(self.curves.get(self.mode.unwrap_or(src.mode)), pos, self.hw_min, self.hw_max)
+    }
+}
+
+pub(crate) struct Outputs { primary: BacklightWriter, mirrors: Vec<Mirror> }
+
+impl Outputs {
+    pub(crate) fn open(cfg: &Config, bl: &Backlight, hw_min: i32, hw_max: i32, now: Instant) -> Self {
+        // keeps retrying in the loop if the node isn't there yet
+        let primary = BacklightWriter::open(bl.brightness_path(), WriteFormat::from_config(cfg), Readback::from_config(cfg, bl), now);
+        Self { primary, mirrors: open_mirrors(cfg, hw_min, hw_max, now) }
+    }
+
+    // config reload: the primary node stays open, mirrors are rebuilt
+    pub(crate) fn reconfigure(&mut self, cfg: &Config, bl: &Backlight, hw_min: i32, hw_max: i32, now: Instant) {
+        self.primary.reconfigure(WriteFormat::from_config(cfg), Readback::from_config(cfg, bl));
+        self.mirrors = open_mirrors(cfg, hw_min, hw_max, now);
+    }
+
+    // last level that reached the panel backlight
+    pub(crate) fn last(&self) -> i32 { self.primary.last() }
+
+    // the panel node's value in hw units, it holds levels after `backlight.scale`
+    pub(crate) fn unscale(&self, raw: i32) -> i32 { self.primary.format().scale.unapply(raw) }
+
+    // the panel node's value in hw units if it isn't what we last wrote there
+    // compared in node units, un-scaling / and >> isn't exact
+    pub(crate) fn foreign(&self, raw: i32) -> Option<i32> {
+        (raw != self.primary.format().scale.apply(self.primary.last())).then(|| self.unscale(raw))
+    }
+
+    // level to resend: the primary's, or its current one while a mirror is behind
+    pub(crate) fn pending(&self) -> Option<i32> {
+        self.primary.pending().or_else(|| {
+            let last = self.primary.last();
+            (last >= 0 && self.mirrors.iter().any(|m| m.writer.pending().is_some())).then_some(last)
+        })
+    }
+
+    pub(crate) fn retry_in(&self, now: Instant) -> Option<Duration> {
+        std::iter::once(&self.primary).chain(self.mirrors.iter().map(|m| &m.writer)).filter_map(|w| w.retry_in(now)).min()
+    }
+
+    // write the panel level and follow it on every mirror
+    // returns the panel write's result and how many mirror writes failed
+    pub(crate) fn write(&mut self, val: i32, src: &Source, now: Instant, sleep: &dyn Fn(Duration), dbg: bool) -> (io::Result<bool>, u32) {
+        let res = self.primary.write(val, now, sleep, dbg);
+        let mut failed = 0;
+        for m in &mut self.mirrors {
+            let level = m.level(val, src);
+            if m.writer.write(level, now, sleep, dbg).is_err() { failed += 1; }
+        }
+        (res, failed)
+    }
+}
+
+fn open_mirrors(cfg: &Config, hw_min: i32, hw_max: i32, now: Instant) -> Vec<Mirror> {
+    let Some(list) = cfg.mirrors.as_deref() else { return Vec::new(); };
+    let base = WriteFormat::from_config(cfg);
+    // custom points are hw levels of the panel, parse them against its range once and share the fractions
+    let custom = load_custom_curve(cfg, hw_min, hw_max);
+    mirror_entries(list).filter_map(|entry| {
+        let spec = parse_mirror(entry).map_err(|e| log_e(&format!("[Outputs] {}, skipping it", e))).ok()?;
+        let path = spec.node();
+        let (min, max) = spec.range.unwrap_or((hw_min, hw_max));
+        let format = WriteFormat { encoding: spec.encoding.unwrap_or(base.encoding), scale: spec.scale.unwrap_or(base.scale) };
+        log_d(&format!("[Outputs] Mirroring to {} ({}-{}, mode {:?}, {:?})", path.display(), min, max, spec.mode, format));
+        Some(Mirror {
+            writer: BacklightWriter::open(path, format, None, now),
+            hw_min: min,
+            hw_max: max,
+            mode: spec.mode,
+            curves: CurveRegistry::with_custom(cfg, custom.clone()),
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::{persist_bright_mode_prop, persist_curve_points_prop, persist_mirrors_prop};
+    use crate::properties::MemoryProps;
+
+    #[test]
+    fn parses_mirror_entries() {
+        assert_eq!(parse_mirror("/sys/class/backlight/panel1").unwrap().path, PathBuf::from("/sys/class/backlight/panel1"));
+        assert_eq!(parse_mirror(" /sys/x/brightness ; range=1-255 ; mode=3 ; format=hex ; scale=<<2"), Ok(MirrorSpec {
+            path: PathBuf::from("/sys/x/brightness"),
+            range: Some((1, 255)),
+            mode: Some(ScalingMode::CieLightness),
+            encoding: Some(Encoding::Hex),
+            scale: Some(Scale::Shl(2)),
+        }));
+        for bad in ["", "panel1", "/x;range=255-1", "/x;range=1", "/x;mode=9", "/x;format=oct", "/x;scale=2", "/x;color=red", "/x;range"] {
+            assert!(parse_mirror(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn mirror_shares_the_panel_custom_curve() {
+        // 256 and 2047 are panel levels, outside a 0-255 mirror's range
+        let props = MemoryProps::with(&[
+            (persist_bright_mode_prop(), "2"),
+            (persist_curve_points_prop(), "0:1, 0.5:256, 1:2047"),
+            (persist_mirrors_prop(), "/nonexistent/panel1;range=0-255"),
+        ]);
+        let cfg = Config::load_files(&props, &[]);
+        let curves = CurveRegistry::load(&cfg, 1, 2047);
+        let mirrors = open_mirrors(&cfg, 1, 2047, Instant::now());
+        assert_eq!(mirrors[0].curves.get(ScalingMode::Custom).name(), "Custom");
+        let src = Source { curve: curves.get(ScalingMode::Custom), mode: ScalingMode::Custom, hw_min: 1, hw_max: 2047 };
+        // same fraction of the range: (256 - 1) / 2046 of 255
+        assert_eq!(mirrors[0].level(256, &src), 32);
+        assert_eq!(mirrors[0].level(2047, &src), 255);
+        assert_eq!(mirrors[0].level(BRIGHTNESS_OFF, &src), BRIGHTNESS_OFF);
+    }
+}
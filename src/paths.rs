@@ -1,22 +1,53 @@
-// file paths & property keys
-pub(crate) fn min_bright_path() -> &'static str { "/sys/class/leds/lcd-backlight/min_brightness" }
-pub(crate) fn max_bright_path() -> &'static str { "/sys/class/leds/lcd-backlight/max_hw_brightness" }
-pub(crate) fn bright_path() -> &'static str { "/sys/class/leds/lcd-backlight/brightness" }
-pub(crate) fn sys_prop_max() -> &'static str { "sys.oplus.multibrightness" }
-pub(crate) fn sys_prop_min() -> &'static str { "sys.oplus.multibrightness.min" }
-pub(crate) fn persist_max() -> &'static str { "persist.sys.rianixia.multibrightness.max" }
-pub(crate) fn persist_min() -> &'static str { "persist.sys.rianixia.multibrightness.min" }
-pub(crate) fn log_tag() -> &'static str { "Xia-DisplayAdaptor" }
-pub(crate) fn persist_dbg() -> &'static str { "persist.sys.rianixia.display-debug" } //set true for debug logs
-pub(crate) fn oplus_bright_path() -> &'static str { "/data/addon/oplus_display/oplus_brightness" } // add for OS14 and under
-pub(crate) fn persist_oplus_min() -> &'static str { "persist.sys.rianixia-display.min" }  // add for OS14 and under
-pub(crate) fn persist_oplus_max() -> &'static str { "persist.sys.rianixia-display.max" } // add for OS14 and under
-pub(crate) fn is_oplus_panel_prop() -> &'static str { "persist.sys.rianixia.is-displaypanel.support" } // add for OS14 and under
-pub(crate) fn persist_custom_devmax_prop() -> &'static str { "persist.sys.rianixia.custom.devmax.brightness" } // adjust device max value for scaling
-pub(crate) fn persist_custom_devmin_prop() -> &'static str { "persist.sys.rianixia.custom.devmin.brightness" } // adjust device min value for scaling
-pub(crate) fn display_type_prop() -> &'static str { "persist.sys.rianixia.display.type" } // value = IPS or AMOLED (usually not needed)
-pub(crate) fn persist_hw_min() -> &'static str { "persist.sys.rianixia.hw_min" } 
-pub(crate) fn persist_hw_max() -> &'static str { "persist.sys.rianixia.hw_max" }
-pub(crate) fn persist_bright_mode_prop() -> &'static str { "persist.sys.rianixia.brightness.mode" } // 0=Curved, 1=Linear, 2=Custom
-pub(crate) fn persist_lux_aod_prop() -> &'static str { "persist.sys.rianixia.oplus.lux_aod" } // for lux aod logic
-pub(crate) fn persist_lux_aod_brightness_prop() -> &'static str { "persist.sys.rianixia.oplus.lux_aod.brightness" } // New prop for overriding 1
\ No newline at end of file
+// file paths & property keys
+pub(crate) fn sysfs_root() -> &'static str { "/sys" } // backlight discovery root
+pub(crate) fn default_backlight_dir() -> &'static str { "/sys/class/leds/lcd-backlight" } // used when discovery finds nothing
+pub(crate) fn backlight_override_prop() -> &'static str { "persist.sys.rianixia.backlight.device" } // device name or absolute dir, skips discovery
+pub(crate) fn persist_readback_prop() -> &'static str { "persist.sys.rianixia.backlight.readback" } // off, brightness, actual_brightness or an absolute path
+pub(crate) fn persist_readback_delay_prop() -> &'static str { "persist.sys.rianixia.backlight.readback_delay_ms" } // wait before each readback
+pub(crate) fn persist_readback_retries_prop() -> &'static str { "persist.sys.rianixia.backlight.readback_retries" } // rewrites after a mismatch
+pub(crate) fn persist_mirrors_prop() -> &'static str { "persist.sys.rianixia.backlight.mirrors" } // extra nodes, "<path>[;range=MIN-MAX][;mode=N][;format=F][;scale=S], ..."
+pub(crate) fn persist_write_format_prop() -> &'static str { "persist.sys.rianixia.backlight.format" } // decimal, newline, nul or hex
+pub(crate) fn persist_write_scale_prop() -> &'static str { "persist.sys.rianixia.backlight.scale" } // *N, /N, <<N or >>N before writing
+pub(crate) fn sys_prop_max() -> &'static str { "sys.oplus.multibrightness" }
+pub(crate) fn sys_prop_min() -> &'static str { "sys.oplus.multibrightness.min" }
+pub(crate) fn persist_max() -> &'static str { "persist.sys.rianixia.multibrightness.max" }
+pub(crate) fn persist_min() -> &'static str { "persist.sys.rianixia.multibrightness.min" }
+pub(crate) fn config_path() -> &'static str { "/vendor/etc/xia_display_adaptor.conf" } // per-device config shipped by maintainers
+pub(crate) fn config_overlay_path() -> &'static str { "/data/vendor/xia_display_adaptor.conf" } // user overlay, overrides the vendor file
+#[cfg(not(target_os = "android"))]
+pub(crate) fn host_props_env() -> &'static str { "XIA_PROPS" } // getprop dump to load when running off device
+pub(crate) fn log_tag() -> &'static str { "Xia-DisplayAdaptor" }
+pub(crate) fn persist_dbg() -> &'static str { "persist.sys.rianixia.display-debug" } //set true for debug logs
+pub(crate) fn oplus_bright_path() -> &'static str { "/data/addon/oplus_display/oplus_brightness" } // add for OS14 and under
+pub(crate) fn persist_oplus_min() -> &'static str { "persist.sys.rianixia-display.min" }  // add for OS14 and under
+pub(crate) fn persist_oplus_max() -> &'static str { "persist.sys.rianixia-display.max" } // add for OS14 and under
+pub(crate) fn is_oplus_panel_prop() -> &'static str { "persist.sys.rianixia.is-displaypanel.support" } // add for OS14 and under
+pub(crate) fn persist_custom_devmax_prop() -> &'static str { "persist.sys.rianixia.custom.devmax.brightness" } // adjust device max value for scaling
+pub(crate) fn persist_custom_devmin_prop() -> &'static str { "persist.sys.rianixia.custom.devmin.brightness" } // adjust device min value for scaling
+pub(crate) fn persist_state_sources_prop() -> &'static str { "persist.sys.rianixia.display.state_sources" } // screen state sources by priority, prop,drm,fb,bl_power
+pub(crate) fn display_type_prop() -> &'static str { "persist.sys.rianixia.display.type" } // value = IPS or AMOLED (usually not needed)
+pub(crate) fn persist_hw_min() -> &'static str { "persist.sys.rianixia.hw_min" } 
+pub(crate) fn persist_hw_max() -> &'static str { "persist.sys.rianixia.hw_max" }
+pub(crate) fn persist_bright_mode_prop() -> &'static str { "persist.sys.rianixia.brightness.mode" } // 0=Curved, 1=Linear, 2=Custom, 3=CIE L*, 4=sRGB, 5=Log
+pub(crate) fn persist_input_source_prop() -> &'static str { "persist.sys.rianixia.brightness.source" } // auto, prop, oplus, node:<path> or setting:<ns>/<key>, ":int|float|percent" suffix
+pub(crate) fn persist_float_space_prop() -> &'static str { "persist.sys.rianixia.brightness.float_space" } // linear or slider, how float inputs are scaled
+pub(crate) fn persist_isfloat_prop() -> &'static str { "persist.sys.rianixia.brightness.isfloat" } // float brightness in debug.tracing.screen_brightness
+pub(crate) fn persist_curve_points_prop() -> &'static str { "persist.sys.rianixia.brightness.curve" } // mode 2 points, "in:out,in:out,..."
+pub(crate) fn persist_curve_interp_prop() -> &'static str { "persist.sys.rianixia.brightness.curve.interp" } // linear or cubic
+pub(crate) fn persist_curve_gamma_prop() -> &'static str { "persist.sys.rianixia.brightness.gamma" } // Curved mode exponent, default 2.2
+pub(crate) fn persist_curve_log_base_prop() -> &'static str { "persist.sys.rianixia.brightness.log_base" } // Log mode base, default 10
+pub(crate) fn persist_ramp_duration_prop() -> &'static str { "persist.sys.rianixia.ramp.duration" } // ms, 0 = instant
+pub(crate) fn persist_ramp_easing_prop() -> &'static str { "persist.sys.rianixia.ramp.easing" } // linear, ease-out or exp
+pub(crate) fn persist_ramp_frame_prop() -> &'static str { "persist.sys.rianixia.ramp.frame" } // ms between ramp frames
+pub(crate) fn persist_lux_aod_prop() -> &'static str { "persist.sys.rianixia.oplus.lux_aod" } // for lux aod logic
+pub(crate) fn persist_lux_aod_brightness_prop() -> &'static str { "persist.sys.rianixia.oplus.lux_aod.brightness" } // New prop for overriding 1
+pub(crate) fn persist_aod_policy_prop() -> &'static str { "persist.sys.rianixia.aod.policy" } // per-state overrides, e.g. "4:fixed:10"
+pub(crate) fn settings_dir() -> &'static str { "/data/system/users/0" } // settings provider files of the owner, settings_<namespace>.xml
+pub(crate) fn persist_settings_ttl_prop() -> &'static str { "persist.sys.rianixia.settings.ttl_ms" } // android settings cache lifetime
+pub(crate) fn persist_control_enable_prop() -> &'static str { "persist.sys.rianixia.control.enable" } // "true" opens the control socket
+pub(crate) fn persist_control_socket_prop() -> &'static str { "persist.sys.rianixia.control.socket" } // "@name" (abstract), a path, or "off"
+pub(crate) fn control_socket_default() -> &'static str { "@xia_display_adaptor" }
+pub(crate) fn control_wake_prop() -> &'static str { "sys.rianixia.display.wake" } // bumped to wake default mode for socket commands
+pub(crate) fn status_prop_prefix() -> &'static str { "sys.rianixia.display." } // read-only status, see status.rs
+pub(crate) fn lease_request_prop() -> &'static str { "sys.rianixia.lease.request" } // "<client>:<hw|input>:<level>:<ms>[:<priority>]" or "<client>:release"
+pub(crate) fn lease_store_path() -> &'static str { "/data/vendor/xia_display_adaptor.leases" } // live leases, survives restarts
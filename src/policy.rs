@@ -0,0 +1,293 @@
+use crate::config::Config;
+use crate::logging::log_e;
+use crate::state::ScreenState;
+
+// what to write for the current screen state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Action {
+    Off,        // brightness 0
+    Keep,       // leave the last written value
+    Fixed(i32), // a specific hw level
+    Scaled,     // current brightness through the active curve
+}
+impl Action {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "off" => Some(Action::Off),
+            "keep" => Some(Action::Keep),
+            "scaled" => Some(Action::Scaled),
+            other => other.strip_prefix("fixed:")
+                .and_then(|v| v.trim().parse::<i32>().ok())
+                .filter(|v| *v > 0)
+                .map(Action::Fixed),
+        }
+    }
+}
+
+// decision plus the reason, for debug logs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Decision { pub(crate) action: Action, pub(crate) reason: &'static str }
+fn decision(action: Action, reason: &'static str) -> Decision { Decision { action, reason } }
+
+// per-device state -> action overrides, e.g. "4:fixed:10, 1:keep" or "doze_suspend:fixed:10"
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct PolicyTable { rules: Vec<(ScreenState, Action)> }
+impl PolicyTable {
+    pub(crate) fn parse(spec: &str) -> Self {
+        let mut rules = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let parsed = entry.split_once(':').and_then(|(state, action)| {
+                Some((ScreenState::parse(state)?, Action::parse(action)?))
+            });
+            match parsed {
+                Some((state, _)) if state.is_display_on() => {
+                    log_e(&format!("[Policy] State {} ({}) keeps the display on and is always scaled, ignoring override", state.raw(), state.name()))
+                },
+                Some((state, action)) => {
+                    rules.retain(|(s, _)| *s != state);
+                    rules.push((state, action));
+                },
+                None => log_e(&format!("[Policy] Invalid policy entry '{}', expected <state number or name>:off|keep|scaled|fixed:N", entry)),
+            }
+        }
+        Self { rules }
+    }
+
+    fn get(&self, state: ScreenState) -> Option<Action> {
+        self.rules.iter().find(|(s, _)| *s == state).map(|(_, a)| *a)
+    }
+}
+
+// device settings the policy depends on
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Settings {
+    pub(crate) ips: bool,
+    pub(crate) lux_aod: bool,
+    pub(crate) lux_aod_brightness: Option<i32>,
+    pub(crate) table: PolicyTable,
+}
+
+impl Settings {
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        Self {
+            ips: cfg.ips,
+            lux_aod: cfg.lux_aod,
+            lux_aod_brightness: cfg.lux_aod_brightness,
+            table: cfg.aod_policy.as_deref().map(PolicyTable::parse).unwrap_or_default(),
+        }
+    }
+}
+
+// values read from the system on this pass
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Inputs {
+    pub(crate) panoramic: bool,    // settings secure panoramic_aod_enable
+    pub(crate) lux_sentinel: bool, // screen_brightness reads the 2937.773 Lux AOD marker
+}
+
+// panoramic AOD is costly to query, only ask when the decision depends on it
+pub(crate) fn needs_panoramic(_prev: ScreenState, cur: ScreenState, settings: &Settings) -> bool {
+    if settings.ips || settings.table.get(cur).is_some() { return false; }
+    matches!(cur, ScreenState::Doze | ScreenState::DozeSuspend)
+}
+
+// screen state policy: (previous state, current state, inputs, settings) -> action
+pub(crate) fn decide(_prev: ScreenState, cur: ScreenState, inputs: &Inputs, settings: &Settings) -> Decision {
+    if cur.is_display_on() { return decision(Action::Scaled, "screen on"); }
+    if let Some(action) = settings.table.get(cur) { return decision(action, "policy table override"); }
+    if settings.ips { return decision(Action::Off, "IPS panel has no AOD"); }
+
+    match cur {
+        ScreenState::Doze | ScreenState::DozeSuspend => {
+            if settings.lux_aod && inputs.panoramic {
+                match settings.lux_aod_brightness {
+                    Some(v) if v > 0 => decision(Action::Fixed(v), "Lux + Panoramic AOD, forced brightness"),
+                    _ => decision(Action::Keep, "Lux + Panoramic AOD without brightness prop"),
+                }
+            } else if cur == ScreenState::Doze && settings.lux_aod {
+                if inputs.lux_sentinel {
+                    decision(Action::Fixed(settings.lux_aod_brightness.unwrap_or(1)), "Lux AOD marker detected")
+                } else {
+                    decision(Action::Scaled, "doze with Lux AOD, following brightness")
+                }
+            } else if inputs.panoramic {
+                decision(Action::Keep, "Panoramic AOD on")
+            } else {
+                decision(Action::Off, "doze with Panoramic AOD off")
+            }
+        },
+        _ => decision(Action::Off, "screen off"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ScreenState::{Doze, DozeSuspend, Off, On, OnSuspend, Unknown, Vr};
+
+    fn settings(ips: bool, lux_aod: bool, lux_aod_brightness: Option<i32>) -> Settings {
+        Settings { ips, lux_aod, lux_aod_brightness, table: PolicyTable::default() }
+    }
+
+    fn inputs(panoramic: bool, lux_sentinel: bool) -> Inputs {
+        Inputs { panoramic, lux_sentinel }
+    }
+
+    // the nested if chain run_default_mode used before the policy was extracted,
+    // VR and ON_SUSPEND left out since it blanked them like a screen turning off
+    #[allow(clippy::if_same_then_else)]
+    fn legacy(prev: i32, cur: i32, i: &Inputs, s: &Settings) -> Action {
+        if cur == 2 {
+            Action::Scaled
+        } else if s.ips {
+            Action::Off
+        } else if cur == 0 || cur == 1 {
+            Action::Off
+        } else if cur == 3 || cur == 4 {
+            if s.lux_aod && i.panoramic {
+                if let Some(target_lux) = s.lux_aod_brightness {
+                    if target_lux > 0 { Action::Fixed(target_lux) } else { Action::Keep }
+                } else {
+                    Action::Keep
+                }
+            } else if cur == 3 && s.lux_aod {
+                if i.lux_sentinel { Action::Fixed(s.lux_aod_brightness.unwrap_or(1)) } else { Action::Scaled }
+            } else if i.panoramic {
+                Action::Keep
+            } else {
+                Action::Off
+            }
+        } else if prev == 2 {
+            if i.panoramic { Action::Keep } else { Action::Off }
+        } else {
+            Action::Keep
+        }
+    }
+
+    #[test]
+    fn matches_legacy_chain_for_every_combination() {
+        let bools = [false, true];
+        for prev in ScreenState::ALL {
+            for cur in ScreenState::ALL.into_iter().filter(|s| !matches!(s, Vr | OnSuspend)) {
+                for ips in bools {
+                    for lux in bools {
+                        for lux_bright in [None, Some(0), Some(-5), Some(25)] {
+                            for panoramic in bools {
+                                for sentinel in bools {
+                                    let s = settings(ips, lux, lux_bright);
+                                    let i = inputs(panoramic, sentinel);
+                                    if !needs_panoramic(prev, cur, &s) {
+                                        let flipped = inputs(!panoramic, sentinel);
+                                        assert_eq!(decide(prev, cur, &i, &s), decide(prev, cur, &flipped, &s));
+                                    }
+                                    assert_eq!(
+                                        decide(prev, cur, &i, &s).action,
+                                        legacy(prev.raw(), cur.raw(), &i, &s),
+                                        "prev={:?} cur={:?} ips={} lux={} lux_bright={:?} panoramic={} sentinel={}",
+                                        prev, cur, ips, lux, lux_bright, panoramic, sentinel,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn screen_on_is_always_scaled() {
+        for prev in ScreenState::ALL {
+            for cur in [On, Vr, OnSuspend] {
+                for panoramic in [false, true] {
+                    let i = inputs(panoramic, true);
+                    assert_eq!(decide(prev, cur, &i, &settings(true, true, Some(9))).action, Action::Scaled);
+                    assert_eq!(decide(prev, cur, &i, &settings(false, false, None)).action, Action::Scaled);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ips_turns_off_outside_on() {
+        for cur in [Unknown, Off, Doze, DozeSuspend] {
+            assert_eq!(decide(On, cur, &inputs(true, true), &settings(true, true, Some(9))).action, Action::Off);
+        }
+    }
+
+    #[test]
+    fn amoled_off_states() {
+        assert_eq!(decide(On, Unknown, &inputs(true, false), &settings(false, false, None)).action, Action::Off);
+        assert_eq!(decide(On, Off, &inputs(true, false), &settings(false, false, None)).action, Action::Off);
+    }
+
+    #[test]
+    fn doze_follows_panoramic() {
+        let s = settings(false, false, None);
+        assert_eq!(decide(On, Doze, &inputs(true, false), &s).action, Action::Keep);
+        assert_eq!(decide(On, DozeSuspend, &inputs(true, false), &s).action, Action::Keep);
+        assert_eq!(decide(On, Doze, &inputs(false, false), &s).action, Action::Off);
+        assert_eq!(decide(On, DozeSuspend, &inputs(false, false), &s).action, Action::Off);
+    }
+
+    #[test]
+    fn lux_and_panoramic_force_brightness() {
+        assert_eq!(decide(On, Doze, &inputs(true, false), &settings(false, true, Some(40))).action, Action::Fixed(40));
+        assert_eq!(decide(On, DozeSuspend, &inputs(true, false), &settings(false, true, Some(40))).action, Action::Fixed(40));
+        assert_eq!(decide(On, Doze, &inputs(true, false), &settings(false, true, Some(0))).action, Action::Keep);
+        assert_eq!(decide(On, DozeSuspend, &inputs(true, false), &settings(false, true, None)).action, Action::Keep);
+    }
+
+    #[test]
+    fn lux_doze_sentinel() {
+        assert_eq!(decide(On, Doze, &inputs(false, true), &settings(false, true, None)).action, Action::Fixed(1));
+        assert_eq!(decide(On, Doze, &inputs(false, true), &settings(false, true, Some(7))).action, Action::Fixed(7));
+        assert_eq!(decide(On, Doze, &inputs(false, false), &settings(false, true, Some(7))).action, Action::Scaled);
+        // doze_suspend never looks at the marker
+        assert_eq!(decide(On, DozeSuspend, &inputs(false, true), &settings(false, true, Some(7))).action, Action::Off);
+    }
+
+    #[test]
+    fn table_overrides_state() {
+        let s = Settings { table: PolicyTable::parse("4:fixed:10, 1:keep, 3:scaled"), ..settings(false, false, None) };
+        assert_eq!(decide(On, DozeSuspend, &inputs(false, false), &s).action, Action::Fixed(10));
+        assert_eq!(decide(On, Off, &inputs(false, false), &s).action, Action::Keep);
+        assert_eq!(decide(On, Doze, &inputs(false, false), &s).action, Action::Scaled);
+        assert_eq!(decide(On, Unknown, &inputs(false, false), &s).action, Action::Off);
+        // overrides win over IPS too
+        let ips = Settings { ips: true, ..s };
+        assert_eq!(decide(On, DozeSuspend, &inputs(false, false), &ips).action, Action::Fixed(10));
+        // VR and ON_SUSPEND rows are dropped like ON
+        let on = Settings { table: PolicyTable::parse("5:off, on_suspend:fixed:10"), ..settings(false, false, None) };
+        assert_eq!(on.table, PolicyTable::default());
+        assert_eq!(decide(On, Vr, &inputs(false, false), &on).action, Action::Scaled);
+        assert_eq!(decide(Doze, OnSuspend, &inputs(false, false), &on).action, Action::Scaled);
+    }
+
+    #[test]
+    fn table_parse_skips_bad_entries() {
+        let t = PolicyTable::parse("2:off, 4:fixed:0, 3:bogus, x:keep, 4:fixed:12, 4:off");
+        assert_eq!(t.get(On), None);
+        assert_eq!(t.get(Doze), None);
+        assert_eq!(t.get(DozeSuspend), Some(Action::Off));
+        // states by name work too
+        let named = PolicyTable::parse("doze_suspend:fixed:10, off:keep, on_suspend:keep, on:off");
+        assert_eq!(named.get(DozeSuspend), Some(Action::Fixed(10)));
+        assert_eq!(named.get(Off), Some(Action::Keep));
+        assert_eq!(named.get(OnSuspend), None);
+        assert_eq!(named.get(On), None);
+    }
+
+    #[test]
+    fn panoramic_only_queried_when_needed() {
+        let s = settings(false, false, None);
+        assert!(!needs_panoramic(Unknown, On, &s));
+        assert!(!needs_panoramic(On, Unknown, &s));
+        assert!(needs_panoramic(Unknown, Doze, &s));
+        assert!(!needs_panoramic(On, Vr, &s));
+        assert!(!needs_panoramic(On, OnSuspend, &s));
+        assert!(!needs_panoramic(On, Doze, &settings(true, false, None)));
+        let t = Settings { table: PolicyTable::parse("3:off"), ..settings(false, false, None) };
+        assert!(!needs_panoramic(On, Doze, &t));
+    }
+}
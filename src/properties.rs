@@ -1,21 +1,295 @@
-use std::ffi::{CString, CStr};
-use std::os::raw::{c_char, c_uchar};
-use crate::ffi::{__system_property_get, __system_property_set};
-
-// system property utilities
-pub(crate) fn get_prop(key: &str) -> Option<String> {
-    const PROP_VALUE_MAX: usize = 92;
-    let c_key = CString::new(key).ok()?;
-    let mut buffer = vec![0u8; PROP_VALUE_MAX];
-    let len = unsafe { __system_property_get(c_key.as_ptr() as *const c_uchar, buffer.as_mut_ptr() as *mut c_uchar) };
-    if len > 0 {
-        let c_str = unsafe { CStr::from_ptr(buffer.as_ptr() as *const c_char) };
-        Some(c_str.to_string_lossy().into_owned())
-    } else { None }
-}
-pub(crate) fn get_prop_int(key: &str) -> Option<i32> { get_prop(key)?.parse::<i32>().ok() }
-pub(crate) fn set_prop(key: &str, val: &str) -> bool {
-    let c_key = CString::new(key).ok().unwrap();
-    let c_val = CString::new(val).ok().unwrap();
-    unsafe { __system_property_set(c_key.as_ptr() as *const c_uchar, c_val.as_ptr() as *const c_uchar) == 0 }
-}
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+use crate::constants::PROP_POLL_MS;
+#[cfg(not(target_os = "android"))]
+use crate::logging::log_e;
+#[cfg(not(target_os = "android"))]
+use crate::paths::host_props_env;
+
+#[cfg(target_os = "android")]
+pub use bionic::BionicProps;
+
+// property store backend
+pub trait PropertyStore {
+    fn get(&self, key: &str) -> Option<String>;
+    fn set(&self, key: &str, val: &str) -> bool;
+
+    // change counter, snapshot it before reading props and pass it to wait_change
+    fn change_serial(&self) -> Option<u32> { None }
+
+    // block until one of `keys` may have changed since `serial`, false on timeout
+    // backends without per-property serials wake for any property, those without notification poll instead
+    fn wait_change(&self, _serial: Option<u32>, _keys: &[&str], timeout: Duration) -> bool {
+        sleep(timeout.min(Duration::from_millis(PROP_POLL_MS)));
+        true
+    }
+}
+
+// bionic system properties (on device)
+#[cfg(target_os = "android")]
+mod bionic {
+    use std::ffi::{CString, CStr};
+    use std::os::raw::{c_char, c_uchar, c_void};
+    use std::sync::OnceLock;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+    use crate::constants::PROP_POLL_MS;
+    use crate::ffi::{
+        __system_property_find, __system_property_get, __system_property_serial, __system_property_set, dlsym_default,
+        SystemPropertyAreaSerialFn, SystemPropertyWaitFn,
+    };
+    use super::PropertyStore;
+
+    const PROP_VALUE_MAX: usize = 92;
+
+    pub struct BionicProps;
+
+    fn bionic_wait_fns() -> Option<(SystemPropertyAreaSerialFn, SystemPropertyWaitFn)> {
+        static FNS: OnceLock<Option<(SystemPropertyAreaSerialFn, SystemPropertyWaitFn)>> = OnceLock::new();
+        *FNS.get_or_init(|| {
+            let serial = dlsym_default(c"__system_property_area_serial")?;
+            let wait = dlsym_default(c"__system_property_wait")?;
+            // symbols looked up by name with the signatures bionic declares
+            unsafe {
+                Some((
+                    std::mem::transmute::<*mut c_void, SystemPropertyAreaSerialFn>(serial),
+                    std::mem::transmute::<*mut c_void, SystemPropertyWaitFn>(wait),
+                ))
+            }
+        })
+    }
+
+    // each property's own serial, None while it doesn't exist
+    fn prop_serials(names: &[CString]) -> Vec<Option<u32>> {
+        names.iter().map(|n| {
+            let pi = unsafe { __system_property_find(n.as_ptr()) };
+            (!pi.is_null()).then(|| unsafe { __system_property_serial(pi) })
+        }).collect()
+    }
+
+    impl PropertyStore for BionicProps {
+        fn get(&self, key: &str) -> Option<String> {
+            let c_key = CString::new(key).ok()?;
+            let mut buffer = vec![0u8; PROP_VALUE_MAX];
+            let len = unsafe { __system_property_get(c_key.as_ptr() as *const c_uchar, buffer.as_mut_ptr() as *mut c_uchar) };
+            if len > 0 {
+                let c_str = unsafe { CStr::from_ptr(buffer.as_ptr() as *const c_char) };
+                Some(c_str.to_string_lossy().into_owned())
+            } else { None }
+        }
+
+        fn set(&self, key: &str, val: &str) -> bool {
+            let (Ok(c_key), Ok(c_val)) = (CString::new(key), CString::new(val)) else { return false; };
+            unsafe { __system_property_set(c_key.as_ptr() as *const c_uchar, c_val.as_ptr() as *const c_uchar) == 0 }
+        }
+
+        fn change_serial(&self) -> Option<u32> {
+            let (area_serial, _) = bionic_wait_fns()?;
+            Some(unsafe { area_serial() })
+        }
+
+        // bionic can only block on one property or on all of them, so this waits on the global serial
+        // and goes back to sleep while none of `keys` changed, other processes' props don't cost a full pass
+        fn wait_change(&self, serial: Option<u32>, keys: &[&str], timeout: Duration) -> bool {
+            let (Some((area_serial, wait)), Some(old)) = (bionic_wait_fns(), serial) else {
+                sleep(timeout.min(Duration::from_millis(PROP_POLL_MS)));
+                return true;
+            };
+            // per-property serials first, a change after them still moves the global one
+            let names: Vec<CString> = keys.iter().filter_map(|k| CString::new(*k).ok()).collect();
+            let watched = prop_serials(&names);
+            let mut cur = unsafe { area_serial() };
+            if cur != old { return true; }
+            let deadline = Instant::now() + timeout;
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                let ts = libc::timespec { tv_sec: left.as_secs() as libc::time_t, tv_nsec: left.subsec_nanos() as libc::c_long };
+                let mut new_serial = 0u32;
+                if left.is_zero() || !unsafe { wait(std::ptr::null(), cur, &mut new_serial, &ts) } { return false; }
+                if prop_serials(&names) != watched { return true; }
+                cur = new_serial;
+            }
+        }
+    }
+}
+
+// in-memory properties (off device)
+#[derive(Default)]
+pub struct MemoryProps { map: Mutex<HashMap<String, String>>, serial: Mutex<u32>, changed: Condvar }
+impl MemoryProps {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn with(pairs: &[(&str, &str)]) -> Self {
+        let props = Self::new();
+        for (k, v) in pairs { props.set(k, v); }
+        props
+    }
+}
+impl PropertyStore for MemoryProps {
+    // empty values read back as unset, like bionic
+    fn get(&self, key: &str) -> Option<String> {
+        self.map.lock().unwrap().get(key).filter(|v| !v.is_empty()).cloned()
+    }
+
+    fn set(&self, key: &str, val: &str) -> bool {
+        self.map.lock().unwrap().insert(key.to_string(), val.to_string());
+        *self.serial.lock().unwrap() += 1;
+        self.changed.notify_all();
+        true
+    }
+
+    fn change_serial(&self) -> Option<u32> { Some(*self.serial.lock().unwrap()) }
+
+    fn wait_change(&self, serial: Option<u32>, _keys: &[&str], timeout: Duration) -> bool {
+        let Some(old) = serial else { return true; };
+        let guard = self.serial.lock().unwrap();
+        let (_guard, res) = self.changed.wait_timeout_while(guard, timeout, |s| *s == old).unwrap();
+        !res.timed_out()
+    }
+}
+
+// file-backed properties: build.prop style `key=value` or a `getprop` dump (`[key]: [value]`)
+// writes stay in memory, the file is never modified
+pub struct FileProps { inner: MemoryProps }
+impl FileProps {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(Self::parse(&content))
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let inner = MemoryProps::new();
+        for (k, v) in content.lines().filter_map(parse_prop_line) {
+            inner.set(k, v);
+        }
+        Self { inner }
+    }
+}
+impl PropertyStore for FileProps {
+    fn get(&self, key: &str) -> Option<String> { self.inner.get(key) }
+    fn set(&self, key: &str, val: &str) -> bool { self.inner.set(key, val) }
+    fn change_serial(&self) -> Option<u32> { self.inner.change_serial() }
+    fn wait_change(&self, serial: Option<u32>, keys: &[&str], timeout: Duration) -> bool { self.inner.wait_change(serial, keys, timeout) }
+}
+
+fn parse_prop_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') { return None; }
+    if let Some(rest) = line.strip_prefix('[') {
+        // getprop dump: [key]: [value]
+        let (key, rest) = rest.split_once("]:")?;
+        let val = rest.trim().strip_prefix('[')?.strip_suffix(']')?;
+        return Some((key.trim(), val));
+    }
+    let (key, val) = line.split_once('=')?;
+    Some((key.trim(), val.trim()))
+}
+
+// the device's property store: bionic on android,
+// on a host the getprop dump named by $XIA_PROPS (or an empty store)
+#[cfg(target_os = "android")]
+pub fn system_props() -> Box<dyn PropertyStore + Send + Sync> { Box::new(BionicProps) }
+
+#[cfg(not(target_os = "android"))]
+pub fn system_props() -> Box<dyn PropertyStore + Send + Sync> { host_props(std::env::var_os(host_props_env())) }
+
+// the dump at `path`, an empty store without one or when it can't be read
+#[cfg(not(target_os = "android"))]
+fn host_props(path: Option<std::ffi::OsString>) -> Box<dyn PropertyStore + Send + Sync> {
+    let Some(path) = path else { return Box::new(MemoryProps::new()); };
+    match FileProps::load(&path) {
+        Ok(p) => Box::new(p),
+        Err(e) => {
+            log_e(&format!("[Props] Failed to load {}: {}, using empty store", Path::new(&path).display(), e));
+            Box::new(MemoryProps::new())
+        },
+    }
+}
+
+// system property utilities
+pub(crate) fn get_prop(props: &dyn PropertyStore, key: &str) -> Option<String> { props.get(key) }
+pub(crate) fn get_prop_int(props: &dyn PropertyStore, key: &str) -> Option<i32> { get_prop(props, key)?.parse::<i32>().ok() }
+pub(crate) fn set_prop(props: &dyn PropertyStore, key: &str, val: &str) -> bool { props.set(key, val) }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use super::*;
+    #[cfg(not(target_os = "android"))]
+    use crate::harness::TempDir;
+
+    #[test]
+    fn parses_both_dump_formats() {
+        let props = FileProps::parse(concat!(
+            "[ro.product.model]: [XIA-1]\n",
+            "[debug.tracing.screen_state]: [2]\n",
+            "persist.sys.rianixia.brightness.mode=1\n",
+            "  ro.board.platform = taro  \n",
+            "[sys.spaced.value]: [a b]\n",
+        ));
+        assert_eq!(props.get("ro.product.model").as_deref(), Some("XIA-1"));
+        assert_eq!(props.get("debug.tracing.screen_state").as_deref(), Some("2"));
+        assert_eq!(props.get("persist.sys.rianixia.brightness.mode").as_deref(), Some("1"));
+        assert_eq!(props.get("ro.board.platform").as_deref(), Some("taro"));
+        assert_eq!(props.get("sys.spaced.value").as_deref(), Some("a b"));
+    }
+
+    #[test]
+    fn skips_comments_blanks_and_empty_values() {
+        let props = FileProps::parse("# build.prop\n\n   \n#x=1\nempty=\n[empty.dump]: []\nbroken line\n[half]: value\ny=2\n");
+        assert_eq!(props.get("#x"), None);
+        assert_eq!(props.get("x"), None);
+        assert_eq!(props.get("empty"), None);
+        assert_eq!(props.get("empty.dump"), None);
+        assert_eq!(props.get("half"), None);
+        assert_eq!(props.get("y").as_deref(), Some("2"));
+        // writes stay in memory, an empty write unsets
+        assert!(props.set("y", ""));
+        assert_eq!(props.get("y"), None);
+    }
+
+    #[test]
+    fn memory_wait_wakes_on_set() {
+        let props = Arc::new(MemoryProps::new());
+        let serial = props.change_serial();
+        assert_eq!(serial, Some(0));
+        // nothing set, the wait times out
+        assert!(!props.wait_change(serial, &[], Duration::from_millis(20)));
+        let setter = Arc::clone(&props);
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            setter.set("a", "1");
+        });
+        let start = Instant::now();
+        assert!(props.wait_change(serial, &["a"], Duration::from_secs(5)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        t.join().unwrap();
+        assert_eq!(props.change_serial(), Some(1));
+        // a stale serial returns right away, no serial means no notification
+        assert!(props.wait_change(serial, &["a"], Duration::from_secs(5)));
+        assert!(props.wait_change(None, &["a"], Duration::from_secs(5)));
+    }
+
+    #[cfg(not(target_os = "android"))]
+    #[test]
+    fn host_props_from_the_xia_props_file() {
+        let tmp = TempDir::new();
+        let dump = tmp.path().join("user.prop");
+        std::fs::write(&dump, "[debug.tracing.screen_state]: [2]\npersist.sys.rianixia.brightness.mode=3\n").unwrap();
+        let props = host_props(Some(dump.into_os_string()));
+        assert_eq!(props.get("debug.tracing.screen_state").as_deref(), Some("2"));
+        assert_eq!(props.get("persist.sys.rianixia.brightness.mode").as_deref(), Some("3"));
+        // unset or unreadable, an empty store that still takes writes
+        for props in [host_props(None), host_props(Some(tmp.path().join("missing.prop").into_os_string()))] {
+            assert_eq!(props.get("debug.tracing.screen_state"), None);
+            assert!(props.set("a", "1"));
+            assert_eq!(props.get("a").as_deref(), Some("1"));
+        }
+    }
+}
@@ -1,43 +1,69 @@
-use crate::constants::{FALLBACK_MIN, FALLBACK_MAX};
-use crate::logging::log_d;
-use crate::modes::dbg_on;
-use crate::properties::{get_prop_int, set_prop};
-use crate::paths::{persist_min, persist_max, sys_prop_min, sys_prop_max};
-
-// brightness range struct
-#[derive(Clone, Copy, Debug)]
-pub(crate) struct BrightnessRange { pub(crate) min: i32, pub(crate) max: i32, locked: bool }
-impl BrightnessRange {
-    pub(crate) fn init() -> Self {
-        let s = match (get_prop_int(persist_min()), get_prop_int(persist_max())) {
-            (Some(a), Some(b)) if a < b => Self { min: a, max: b, locked: false },
-            _ => Self { min: FALLBACK_MIN, max: FALLBACK_MAX, locked: false },
-        };
-        if dbg_on() { log_d(&format!("[BrightnessRange] Initialized with range: min={}, max={}", s.min, s.max)); }
-        s
-    }
-
-    pub(crate) fn refresh_range(&mut self) {
-        if self.locked { return; }
-        let pmin = get_prop_int(persist_min());
-        let pmax = get_prop_int(persist_max());
-        let rmin = get_prop_int(sys_prop_min());
-        let rmax = get_prop_int(sys_prop_max());
-
-        if let (Some(rm), Some(rx)) = (rmin, rmax) {
-            if rm < rx {
-                self.min = rm;
-                self.max = rx;
-                if pmin != Some(rm) { set_prop(persist_min(), &rm.to_string()); }
-                if pmax != Some(rx) { set_prop(persist_max(), &rx.to_string()); }
-                self.locked = true;
-            }
-        } else if let (Some(a), Some(b)) = (pmin, pmax) {
-            if a < b { self.min = a; self.max = b; }
-        } else {
-            self.min = FALLBACK_MIN;
-            self.max = FALLBACK_MAX;
-        }
-        if self.min >= self.max { self.min = FALLBACK_MIN; self.max = FALLBACK_MAX; }
-    }
-}
+use crate::constants::{FALLBACK_MIN, FALLBACK_MAX};
+use crate::logging::log_d;
+use crate::config::Config;
+use crate::properties::{PropertyStore, get_prop_int, set_prop};
+use crate::paths::{persist_min, persist_max, sys_prop_min, sys_prop_max};
+
+// brightness range struct
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BrightnessRange { pub(crate) min: i32, pub(crate) max: i32, base: (i32, i32), locked: bool }
+impl BrightnessRange {
+    // config (file or persist props) seeds the range, fallback otherwise
+    pub(crate) fn init(cfg: &Config) -> Self {
+        let base = match (cfg.range_min, cfg.range_max) {
+            (Some(a), Some(b)) if a < b => (a, b),
+            _ => (FALLBACK_MIN, FALLBACK_MAX),
+        };
+        let s = Self { min: base.0, max: base.1, base, locked: false };
+        if cfg.debug { log_d(&format!("[BrightnessRange] Initialized with range: min={}, max={}", s.min, s.max)); }
+        s
+    }
+
+    pub(crate) fn refresh_range(&mut self, props: &dyn PropertyStore) { self.update(props, true); }
+
+    // the same resolution without mirroring the system range into the persist props, for read-only diagnostics
+    pub(crate) fn read_range(&mut self, props: &dyn PropertyStore) { self.update(props, false); }
+
+    fn update(&mut self, props: &dyn PropertyStore, persist: bool) {
+        if self.locked { return; }
+        let pmin = get_prop_int(props, persist_min());
+        let pmax = get_prop_int(props, persist_max());
+        let rmin = get_prop_int(props, sys_prop_min());
+        let rmax = get_prop_int(props, sys_prop_max());
+
+        if let (Some(rm), Some(rx)) = (rmin, rmax) {
+            if rm < rx {
+                self.min = rm;
+                self.max = rx;
+                if persist && pmin != Some(rm) { set_prop(props, persist_min(), &rm.to_string()); }
+                if persist && pmax != Some(rx) { set_prop(props, persist_max(), &rx.to_string()); }
+                self.locked = true;
+            }
+        } else if let (Some(a), Some(b)) = (pmin, pmax) {
+            if a < b { self.min = a; self.max = b; }
+        } else {
+            (self.min, self.max) = self.base;
+        }
+        if self.min >= self.max { (self.min, self.max) = self.base; }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::MemoryProps;
+
+    #[test]
+    fn system_range_is_persisted_only_on_refresh() {
+        let props = MemoryProps::with(&[(sys_prop_min(), "1"), (sys_prop_max(), "4095")]);
+        let cfg = Config::load_files(&props, &[]);
+        let mut range = BrightnessRange::init(&cfg);
+        range.read_range(&props);
+        assert_eq!((range.min, range.max), (1, 4095));
+        assert_eq!((props.get(persist_min()), props.get(persist_max())), (None, None));
+
+        let mut range = BrightnessRange::init(&cfg);
+        range.refresh_range(&props);
+        assert_eq!((props.get(persist_min()).as_deref(), props.get(persist_max()).as_deref()), (Some("1"), Some("4095")));
+    }
+}
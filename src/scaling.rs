@@ -1,68 +1,333 @@
-// scaling functions
-
-pub(crate) fn scale_brightness_linear(
-    val: i32,
-    hw_min: i32,
-    hw_max: i32,
-    input_min: i32,
-    input_max: i32,
-) -> i32 {
-    if val <= input_min { return hw_min; }
-    if val >= input_max { return hw_max; }
-
-    let range_input = (input_max - input_min) as f32;
-    let range_hw = (hw_max - hw_min) as f32;
-    let ratio = (val - input_min) as f32 / range_input;
-
-    (hw_min as f32 + ratio * range_hw).round() as i32
-}
-
-pub(crate) fn scale_brightness_curved(
-    val: i32,
-    hw_min: i32,
-    hw_max: i32,
-    input_min: i32,
-    input_max: i32,
-) -> i32 {
-    if val <= input_min { return hw_min; }
-    if val >= input_max { return hw_max; }
-
-    let range_input = (input_max - input_min) as f32;
-    let range_hw = (hw_max - hw_min) as f32;
-    let ratio = (val - input_min) as f32 / range_input;
-
-    // Standard Gamma 2.2 approximation (Perceptual -> Linear)
-    let gamma: f32 = 2.2; 
-    let curve = ratio.powf(gamma);
-
-    (hw_min as f32 + curve * range_hw).round() as i32
-}
-
-// Custom % Curve:
-// Example
-// Min -> 1
-// Max -> 511
-// @ 75% Input -> 255 Output
-pub(crate) fn scale_brightness_custom(
-    val: i32,
-    hw_min: i32,
-    hw_max: i32,
-    input_min: i32,
-    input_max: i32,
-) -> i32 {
-    if val <= input_min { return hw_min; }
-    if val >= input_max { return hw_max; }
-
-    let range_input = (input_max - input_min) as f32;
-    let normalized = (val - input_min) as f32 / range_input;
-
-    let mid_in = 0.75;
-    let mid_out = 255.0;
-    if normalized <= mid_in {
-        let ratio = normalized / mid_in;
-        (hw_min as f32 + ratio * (mid_out - hw_min as f32)).round() as i32
-    } else {
-        let ratio = (normalized - mid_in) / (1.0 - mid_in);
-        (mid_out + ratio * (hw_max as f32 - mid_out)).round() as i32
-    }
-}
\ No newline at end of file
+use crate::config::Config;
+use crate::curve::{Curve, bisect_inverse};
+use crate::constants::{DEFAULT_GAMMA, DEFAULT_LOG_BASE};
+use crate::logging::{log_d, log_e};
+
+// curve implementations
+
+// Direct 1:1 mapping
+pub(crate) struct Linear;
+impl Curve for Linear {
+    fn name(&self) -> &'static str { "Linear" }
+    fn description(&self) -> String { "direct 1:1 mapping".to_string() }
+    fn forward(&self, x: f32) -> f32 { x }
+    fn inverse(&self, y: f32) -> f32 { y }
+}
+
+// Gamma approximation (Perceptual -> Linear), 2.2 by default
+pub(crate) struct Gamma { pub(crate) gamma: f32 }
+impl Curve for Gamma {
+    fn name(&self) -> &'static str { "Curved" }
+    fn description(&self) -> String { format!("gamma {}", self.gamma) }
+    fn forward(&self, x: f32) -> f32 { x.powf(self.gamma) }
+    fn inverse(&self, y: f32) -> f32 { y.powf(1.0 / self.gamma) }
+}
+
+// CIE 1976 L* lightness, input is L*/100
+pub(crate) struct CieLightness;
+const CIE_KAPPA: f32 = 24389.0 / 27.0;
+const CIE_EPSILON: f32 = 216.0 / 24389.0;
+impl Curve for CieLightness {
+    fn name(&self) -> &'static str { "CIE L*" }
+    fn description(&self) -> String { "CIE 1976 lightness".to_string() }
+    fn forward(&self, x: f32) -> f32 {
+        let l = x * 100.0;
+        if l > 8.0 { ((l + 16.0) / 116.0).powi(3) } else { l / CIE_KAPPA }
+    }
+    fn inverse(&self, y: f32) -> f32 {
+        let l = if y > CIE_EPSILON { 116.0 * y.cbrt() - 16.0 } else { y * CIE_KAPPA };
+        l / 100.0
+    }
+}
+
+// sRGB transfer function (IEC 61966-2-1 EOTF)
+pub(crate) struct Srgb;
+impl Curve for Srgb {
+    fn name(&self) -> &'static str { "sRGB" }
+    fn description(&self) -> String { "sRGB transfer".to_string() }
+    fn forward(&self, x: f32) -> f32 {
+        if x <= 0.04045 { x / 12.92 } else { ((x + 0.055) / 1.055).powf(2.4) }
+    }
+    fn inverse(&self, y: f32) -> f32 {
+        if y <= 0.0031308 { y * 12.92 } else { 1.055 * y.powf(1.0 / 2.4) - 0.055 }
+    }
+}
+
+// logarithmic perception: equal input steps are equal luminance ratios
+pub(crate) struct Log { pub(crate) base: f32 }
+impl Curve for Log {
+    fn name(&self) -> &'static str { "Log" }
+    fn description(&self) -> String { format!("log base {}", self.base) }
+    fn forward(&self, x: f32) -> f32 { (self.base.powf(x) - 1.0) / (self.base - 1.0) }
+    fn inverse(&self, y: f32) -> f32 { (1.0 + y * (self.base - 1.0)).ln() / self.base.ln() }
+}
+
+// curve parameters from config, invalid values are logged and replaced by defaults
+pub(crate) fn gamma_from_config(cfg: &Config) -> Gamma {
+    match cfg.curve_gamma {
+        Some(g) if g > 0.0 && g.is_finite() => Gamma { gamma: g },
+        Some(g) => { log_e(&format!("[Scaling] Invalid gamma {}, using {}", g, DEFAULT_GAMMA)); Gamma { gamma: DEFAULT_GAMMA } },
+        None => Gamma { gamma: DEFAULT_GAMMA },
+    }
+}
+
+pub(crate) fn log_from_config(cfg: &Config) -> Log {
+    match cfg.curve_log_base {
+        Some(b) if b > 1.0 && b.is_finite() => Log { base: b },
+        Some(b) => { log_e(&format!("[Scaling] Invalid log base {} (must be > 1), using {}", b, DEFAULT_LOG_BASE)); Log { base: DEFAULT_LOG_BASE } },
+        None => Log { base: DEFAULT_LOG_BASE },
+    }
+}
+
+// the pre-configurable Custom mode
+const LEGACY_MID_INPUT: f32 = 0.75;
+const LEGACY_MID_LEVEL: i32 = 255;
+
+// custom curve interpolation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Interp { Linear, MonotoneCubic }
+impl Interp {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "linear" => Some(Interp::Linear),
+            "cubic" | "spline" => Some(Interp::MonotoneCubic),
+            _ => None,
+        }
+    }
+}
+
+// user-defined curve, points stored as normalized (input, output) fractions
+#[derive(Clone, Debug)]
+pub(crate) struct CustomCurve { xs: Vec<f32>, ys: Vec<f32>, tangents: Vec<f32>, interp: Interp }
+impl CustomCurve {
+    // spec: "in:out, in:out, ..." with `in` an input fraction (0..1) and
+    // `out` a hw level (integer, e.g. 255) or an output fraction (decimal, e.g. 0.25)
+    pub(crate) fn parse(spec: &str, interp: Interp, hw_min: i32, hw_max: i32) -> Result<Self, String> {
+        if hw_max <= hw_min { return Err(format!("invalid hw range {}-{}", hw_min, hw_max)); }
+        let range_hw = (hw_max - hw_min) as f32;
+        let mut xs = Vec::new();
+        let mut ys = Vec::new();
+        for point in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (x, y) = point.split_once(':').ok_or_else(|| format!("point '{}' is not in:out", point))?;
+            let x: f32 = x.trim().parse().map_err(|_| format!("bad input '{}'", x.trim()))?;
+            if !(0.0..=1.0).contains(&x) { return Err(format!("input {} outside 0..1", x)); }
+            let y = y.trim();
+            let y = if y.contains('.') {
+                let f: f32 = y.parse().map_err(|_| format!("bad output '{}'", y))?;
+                if !(0.0..=1.0).contains(&f) { return Err(format!("output fraction {} outside 0..1", f)); }
+                f
+            } else {
+                let level: i32 = y.parse().map_err(|_| format!("bad output '{}'", y))?;
+                if level < hw_min || level > hw_max {
+                    return Err(format!("output level {} outside hw range {}-{}", level, hw_min, hw_max));
+                }
+                (level - hw_min) as f32 / range_hw
+            };
+            xs.push(x);
+            ys.push(y);
+        }
+        if xs.len() < 2 { return Err("need at least 2 points".to_string()); }
+        for i in 1..xs.len() {
+            if xs[i] <= xs[i - 1] { return Err(format!("inputs not strictly increasing at {}", xs[i])); }
+            if ys[i] < ys[i - 1] { return Err(format!("outputs not monotonic at input {}", xs[i])); }
+        }
+        let tangents = if interp == Interp::MonotoneCubic { monotone_tangents(&xs, &ys) } else { Vec::new() };
+        Ok(Self { xs, ys, tangents, interp })
+    }
+
+    // the fixed curve mode 2 used before points were configurable: 75% input at level 255, straight lines either side
+    pub(crate) fn legacy(hw_min: i32, hw_max: i32) -> Result<Self, String> {
+        let mid = LEGACY_MID_LEVEL.clamp(hw_min, hw_max);
+        Self::parse(&format!("0:{}, {}:{}, 1:{}", hw_min, LEGACY_MID_INPUT, mid, hw_max), Interp::Linear, hw_min, hw_max)
+    }
+
+    // normalized input -> normalized output, flat outside the defined points
+    fn eval(&self, x: f32) -> f32 {
+        let n = self.xs.len();
+        if x <= self.xs[0] { return self.ys[0]; }
+        if x >= self.xs[n - 1] { return self.ys[n - 1]; }
+        let k = self.xs.partition_point(|p| *p <= x) - 1;
+        let (x0, x1, y0, y1) = (self.xs[k], self.xs[k + 1], self.ys[k], self.ys[k + 1]);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        match self.interp {
+            Interp::Linear => y0 + t * (y1 - y0),
+            Interp::MonotoneCubic => {
+                // cubic hermite segment
+                let (t2, t3) = (t * t, t * t * t);
+                let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+                let h10 = t3 - 2.0 * t2 + t;
+                let h01 = -2.0 * t3 + 3.0 * t2;
+                let h11 = t3 - t2;
+                (h00 * y0 + h10 * h * self.tangents[k] + h01 * y1 + h11 * h * self.tangents[k + 1]).clamp(y0, y1)
+            }
+        }
+    }
+}
+
+impl Curve for CustomCurve {
+    fn name(&self) -> &'static str { "Custom" }
+    fn description(&self) -> String { format!("{} points, {:?}", self.xs.len(), self.interp) }
+    fn forward(&self, x: f32) -> f32 { self.eval(x) }
+    fn inverse(&self, y: f32) -> f32 { bisect_inverse(self, y) }
+}
+
+// Fritsch-Carlson tangents, keeps the spline monotonic between points
+fn monotone_tangents(xs: &[f32], ys: &[f32]) -> Vec<f32> {
+    let n = xs.len();
+    let d: Vec<f32> = (0..n - 1).map(|k| (ys[k + 1] - ys[k]) / (xs[k + 1] - xs[k])).collect();
+    let mut m = vec![0.0; n];
+    m[0] = d[0];
+    m[n - 1] = d[n - 2];
+    for k in 1..n - 1 {
+        m[k] = if d[k - 1] * d[k] > 0.0 { (d[k - 1] + d[k]) / 2.0 } else { 0.0 };
+    }
+    for k in 0..n - 1 {
+        if d[k] == 0.0 {
+            m[k] = 0.0;
+            m[k + 1] = 0.0;
+            continue;
+        }
+        let a = m[k] / d[k];
+        let b = m[k + 1] / d[k];
+        let s = a * a + b * b;
+        if s > 9.0 {
+            let t = 3.0 / s.sqrt();
+            m[k] = t * a * d[k];
+            m[k + 1] = t * b * d[k];
+        }
+    }
+    m
+}
+
+// load the custom curve from config, None (with the reason logged) if missing or invalid
+pub(crate) fn load_custom_curve(cfg: &Config, hw_min: i32, hw_max: i32) -> Option<CustomCurve> {
+    let Some(spec) = cfg.curve_points.as_deref() else {
+        if cfg.debug { log_d("[Scaling] No curve points configured, Custom uses the legacy 75% -> 255 curve"); }
+        return CustomCurve::legacy(hw_min, hw_max).ok();
+    };
+    let interp = match cfg.curve_interp.as_deref() {
+        None => Interp::Linear,
+        Some(s) => match Interp::parse(s) {
+            Some(i) => i,
+            None => { log_e(&format!("[Scaling] Unknown curve interpolation '{}', using linear", s)); Interp::Linear },
+        },
+    };
+    match CustomCurve::parse(spec, interp, hw_min, hw_max) {
+        Ok(c) => {
+            if cfg.debug { log_d(&format!("[Scaling] Custom curve loaded: {} points, {:?}", c.xs.len(), interp)); }
+            Some(c)
+        },
+        Err(e) => { log_e(&format!("[Scaling] Custom curve rejected ({}), falling back to Curved", e)); None },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::{persist_bright_mode_prop, persist_curve_interp_prop, persist_curve_points_prop};
+    use crate::curve::scale;
+    use crate::properties::{MemoryProps, PropertyStore};
+
+    fn curve(spec: &str, interp: Interp) -> CustomCurve { CustomCurve::parse(spec, interp, 1, 2047).unwrap() }
+
+    #[test]
+    fn parses_points_as_levels_or_fractions() {
+        // integers are hw levels, decimals fractions of the hw range
+        let c = curve("0:1, 0.5:1024, 1:1.0", Interp::Linear);
+        assert_eq!(c.xs, vec![0.0, 0.5, 1.0]);
+        assert_eq!(c.ys, vec![0.0, 0.5, 1.0]);
+        let c = curve(" 0:0.0 ,0.25:0.1,, 1:2047 ", Interp::Linear);
+        assert_eq!(c.ys, vec![0.0, 0.1, 1.0]);
+        assert!((c.forward(0.125) - 0.05).abs() < 1e-6);
+        // flat outside the points
+        let c = curve("0.2:0.1, 0.8:0.9", Interp::Linear);
+        assert_eq!((c.forward(0.0), c.forward(1.0)), (0.1, 0.9));
+
+        assert_eq!(Interp::parse("spline"), Some(Interp::MonotoneCubic));
+        assert_eq!(Interp::parse("bezier"), None);
+    }
+
+    #[test]
+    fn rejects_bad_points() {
+        for bad in [
+            "", "0:1", "0:1, 1", "0:1, x:2047", "0:1, 1:y",
+            "-0.1:1, 1:2047", "0:1, 1.5:2047",           // inputs outside 0..1
+            "0:0, 1:4095", "0:-0.5, 1:1.0", "0:0.0, 1:1.5", // outputs outside the hw range
+            "0:1, 0.5:100, 0.5:200, 1:2047",              // inputs not strictly increasing
+            "0:1, 0.5:1000, 1:900",                       // outputs going down
+        ] {
+            assert!(CustomCurve::parse(bad, Interp::Linear, 1, 2047).is_err(), "{}", bad);
+        }
+        assert!(CustomCurve::parse("0:0.0, 1:1.0", Interp::Linear, 100, 100).is_err());
+    }
+
+    #[test]
+    fn cubic_stays_monotonic_through_the_points() {
+        // a flat stretch next to a steep one overshoots with plain cubic tangents
+        let spec = "0:0.0, 0.3:0.05, 0.5:0.05, 0.55:0.6, 0.8:0.65, 1:1.0";
+        let c = curve(spec, Interp::MonotoneCubic);
+        let linear = curve(spec, Interp::Linear);
+        let mut prev = c.forward(0.0);
+        for i in 1..=1000 {
+            let x = i as f32 / 1000.0;
+            let y = c.forward(x);
+            assert!(y >= prev, "falls at {}: {} < {}", x, y, prev);
+            prev = y;
+        }
+        for (x, y) in c.xs.iter().zip(&c.ys) {
+            assert!((c.forward(*x) - y).abs() < 1e-6);
+        }
+        // the flat stretch stays flat, the curve differs from straight segments elsewhere
+        assert_eq!(c.forward(0.4), 0.05);
+        assert!((c.forward(0.9) - linear.forward(0.9)).abs() > 1e-3);
+        // the inverse lands back on the input
+        assert!((c.inverse(c.forward(0.7)) - 0.7).abs() < 1e-3);
+    }
+
+    #[test]
+    fn loads_from_config_or_falls_back() {
+        let props = MemoryProps::with(&[
+            (persist_bright_mode_prop(), "2"),
+            (persist_curve_points_prop(), "0:1, 1:2047"),
+            (persist_curve_interp_prop(), "cubic"),
+        ]);
+        let c = load_custom_curve(&Config::load_files(&props, &[]), 1, 2047).unwrap();
+        assert_eq!(c.interp, Interp::MonotoneCubic);
+
+        // unknown interpolation is linear, bad points or none mean no custom curve
+        props.set(persist_curve_interp_prop(), "bezier");
+        assert_eq!(load_custom_curve(&Config::load_files(&props, &[]), 1, 2047).unwrap().interp, Interp::Linear);
+        props.set(persist_curve_points_prop(), "0:1, 1:4095");
+        assert!(load_custom_curve(&Config::load_files(&props, &[]), 1, 2047).is_none());
+        // no points at all is the legacy curve
+        let c = load_custom_curve(&Config::load_files(&MemoryProps::new(), &[]), 1, 2047).unwrap();
+        assert_eq!((c.xs.len(), c.interp), (3, Interp::Linear));
+    }
+
+    // scale_brightness_custom from before the curve was configurable
+    fn old_custom(val: i32, hw_min: i32, hw_max: i32, input_min: i32, input_max: i32) -> i32 {
+        if val <= input_min { return hw_min; }
+        if val >= input_max { return hw_max; }
+        let normalized = (val - input_min) as f32 / (input_max - input_min) as f32;
+        if normalized <= 0.75 {
+            (hw_min as f32 + normalized / 0.75 * (255.0 - hw_min as f32)).round() as i32
+        } else {
+            (255.0 + (normalized - 0.75) / 0.25 * (hw_max as f32 - 255.0)).round() as i32
+        }
+    }
+
+    #[test]
+    fn legacy_curve_matches_the_old_custom_mode() {
+        for (hw_min, hw_max) in [(1, 2047), (1, 4095), (4, 1023)] {
+            let c = CustomCurve::legacy(hw_min, hw_max).unwrap();
+            for val in 0..=2047 {
+                let old = old_custom(val, hw_min, hw_max, 1, 2047);
+                assert_eq!(scale(&c, val, hw_min, hw_max, 1, 2047), old, "{} in {}-{}", val, hw_min, hw_max);
+            }
+        }
+        // a panel topping out below 255 keeps a valid, flat-topped curve
+        let c = CustomCurve::legacy(1, 200).unwrap();
+        assert_eq!((scale(&c, 1535, 1, 200, 1, 2047), scale(&c, 2047, 1, 200, 1, 2047)), (200, 200));
+    }
+}
@@ -0,0 +1,196 @@
+// android settings (secure/system/global) without starting a JVM per lookup
+// the provider's files are read directly, `settings list` is the fallback, one fetch fills a whole namespace
+// values are kept for a TTL and dropped when the screen leaves ON
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use crate::logging::{log_d, log_e};
+use crate::settings_xml::{file_name, read_namespace};
+
+pub(crate) const PANORAMIC_AOD: &str = "panoramic_aod_enable"; // secure, keeps AOD lit in doze
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Namespace { Secure, System, Global }
+impl Namespace {
+    pub(crate) fn name(self) -> &'static str {
+        match self { Namespace::Secure => "secure", Namespace::System => "system", Namespace::Global => "global" }
+    }
+
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        [Namespace::Secure, Namespace::System, Namespace::Global].into_iter().find(|ns| ns.name() == s.trim())
+    }
+}
+
+// fetches every set value of a namespace, the device reads the settings files, tests fake it
+pub(crate) trait SettingsRunner {
+    fn list(&self, ns: Namespace) -> io::Result<HashMap<String, String>>;
+}
+
+pub(crate) struct CommandRunner;
+impl SettingsRunner for CommandRunner {
+    fn list(&self, ns: Namespace) -> io::Result<HashMap<String, String>> {
+        let out = Command::new("settings").arg("list").arg(ns.name()).output()?;
+        if !out.status.success() {
+            return Err(io::Error::other(String::from_utf8_lossy(&out.stderr).trim().to_string()));
+        }
+        Ok(parse_list(&String::from_utf8_lossy(&out.stdout)))
+    }
+}
+
+// settings_<namespace>.xml in a user's settings dir, plain XML or ABX
+// works before the framework is up, anything unreadable goes to the fallback instead
+pub(crate) struct FileRunner<F> { pub(crate) dir: PathBuf, pub(crate) fallback: F }
+impl<F: SettingsRunner> SettingsRunner for FileRunner<F> {
+    fn list(&self, ns: Namespace) -> io::Result<HashMap<String, String>> {
+        read_namespace(&self.dir, ns).or_else(|e| {
+            log_d(&format!("[Settings] Can't read {}: {}, falling back", self.dir.join(file_name(ns)).display(), e));
+            self.fallback.list(ns)
+        })
+    }
+}
+
+// ends a FileRunner without spawning anything, for values polled too often for `settings list`
+pub(crate) struct NoFallback;
+impl SettingsRunner for NoFallback {
+    fn list(&self, _ns: Namespace) -> io::Result<HashMap<String, String>> {
+        Err(io::Error::other("settings files unreadable, not falling back to `settings list`"))
+    }
+}
+
+// `key=value` lines, "null" means unset like `settings get` prints it
+pub(crate) fn parse_list(out: &str) -> HashMap<String, String> {
+    out.lines()
+        .filter_map(|l| l.split_once('='))
+        .filter(|(_, v)| *v != "null")
+        .map(|(k, v)| (k.trim().to_string(), v.to_string()))
+        .collect()
+}
+
+struct Cached { at: Instant, values: HashMap<String, String> }
+
+pub(crate) struct SettingsReader<'a> {
+    runner: &'a dyn SettingsRunner,
+    ttl: Duration,
+    cache: HashMap<Namespace, Cached>,
+    failing: Option<String>, // last fetch error, logged once until a fetch works again
+    dbg: bool,
+}
+
+impl<'a> SettingsReader<'a> {
+    pub(crate) fn new(runner: &'a dyn SettingsRunner, ttl: Duration, dbg: bool) -> Self {
+        Self { runner, ttl, cache: HashMap::new(), failing: None, dbg }
+    }
+
+    // forget everything, the next read fetches again
+    pub(crate) fn invalidate(&mut self) { self.cache.clear(); }
+
+    // several keys of one namespace with at most one fetch
+    pub(crate) fn get_many(&mut self, ns: Namespace, keys: &[&str], now: Instant) -> Vec<Option<String>> {
+        let fresh = self.cache.get(&ns).is_some_and(|c| now.duration_since(c.at) < self.ttl);
+        if !fresh {
+            match self.runner.list(ns) {
+                Ok(values) => {
+                    if self.dbg { log_d(&format!("[Settings] Fetched {} {} settings", values.len(), ns.name())); }
+                    self.cache.insert(ns, Cached { at: now, values });
+                    self.failing = None;
+                },
+                // a failed fetch reads as unset and is retried on the next lookup
+                Err(e) => {
+                    let msg = format!("[Settings] Reading {} settings failed: {}", ns.name(), e);
+                    if self.failing.as_ref() != Some(&msg) { log_e(&msg); }
+                    self.failing = Some(msg);
+                    self.cache.remove(&ns);
+                },
+            }
+        }
+        let values = self.cache.get(&ns).map(|c| &c.values);
+        keys.iter().map(|k| values.and_then(|v| v.get(*k)).cloned()).collect()
+    }
+
+    pub(crate) fn get(&mut self, ns: Namespace, key: &str, now: Instant) -> Option<String> {
+        self.get_many(ns, &[key], now).pop().flatten()
+    }
+
+    pub(crate) fn get_secure(&mut self, key: &str, now: Instant) -> Option<String> { self.get(Namespace::Secure, key, now) }
+    pub(crate) fn get_system(&mut self, key: &str, now: Instant) -> Option<String> { self.get(Namespace::System, key, now) }
+    pub(crate) fn get_global(&mut self, key: &str, now: Instant) -> Option<String> { self.get(Namespace::Global, key, now) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use crate::harness::TempDir;
+
+    struct Fake { runs: Cell<usize>, fail: Cell<bool> }
+    impl SettingsRunner for Fake {
+        fn list(&self, ns: Namespace) -> io::Result<HashMap<String, String>> {
+            self.runs.set(self.runs.get() + 1);
+            if self.fail.get() { return Err(io::Error::other("cmd: Can't find service: settings")); }
+            Ok(parse_list(match ns {
+                Namespace::Secure => "panoramic_aod_enable=1\nreduce_bright_colors_activated=0\nskip_gesture=null\n",
+                Namespace::System => "screen_brightness_mode=1\nringtone=content://media/x?a=b\n",
+                Namespace::Global => "",
+            }))
+        }
+    }
+
+    #[test]
+    fn caches_per_namespace_until_ttl_or_invalidate() {
+        let fake = Fake { runs: Cell::new(0), fail: Cell::new(false) };
+        let mut s = SettingsReader::new(&fake, Duration::from_secs(60), false);
+        let t0 = Instant::now();
+
+        assert_eq!(
+            s.get_many(Namespace::Secure, &["panoramic_aod_enable", "reduce_bright_colors_activated", "skip_gesture"], t0),
+            vec![Some("1".to_string()), Some("0".to_string()), None],
+        );
+        assert_eq!(s.get_secure("panoramic_aod_enable", t0 + Duration::from_secs(59)).as_deref(), Some("1"));
+        assert_eq!(fake.runs.get(), 1);
+
+        // namespaces are fetched separately, values may hold '='
+        assert_eq!(s.get_system("ringtone", t0).as_deref(), Some("content://media/x?a=b"));
+        assert_eq!(s.get_global("zen_mode", t0), None);
+        assert_eq!(fake.runs.get(), 3);
+
+        s.get_secure("panoramic_aod_enable", t0 + Duration::from_secs(60));
+        assert_eq!(fake.runs.get(), 4);
+        s.invalidate();
+        s.get_system("screen_brightness_mode", t0 + Duration::from_secs(61));
+        assert_eq!(fake.runs.get(), 5);
+
+        // failures read as unset and are not cached
+        s.invalidate();
+        fake.fail.set(true);
+        assert_eq!(s.get_secure("panoramic_aod_enable", t0), None);
+        fake.fail.set(false);
+        assert_eq!(s.get_secure("panoramic_aod_enable", t0).as_deref(), Some("1"));
+        assert_eq!(fake.runs.get(), 7);
+    }
+
+    #[test]
+    fn reads_files_and_falls_back_when_unreadable() {
+        let tmp = TempDir::new();
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/settings/abx");
+        std::fs::copy(fixtures.join("settings_secure.xml"), tmp.path().join("settings_secure.xml")).unwrap();
+        std::fs::write(tmp.path().join("settings_system.xml"), b"ABX\0\x32").unwrap();
+        let files = FileRunner { dir: tmp.path().to_path_buf(), fallback: Fake { runs: Cell::new(0), fail: Cell::new(false) } };
+        let mut s = SettingsReader::new(&files, Duration::from_secs(60), false);
+        let t0 = Instant::now();
+
+        assert_eq!(s.get_secure("panoramic_aod_enable", t0).as_deref(), Some("1"));
+        assert_eq!(s.get_secure("enabled_accessibility_services", t0).as_deref(), Some("com.a/.S:com.b/.T&x=\"1\""));
+        assert_eq!(files.fallback.runs.get(), 0);
+        // damaged and missing files both go to `settings list`
+        assert_eq!(s.get_system("ringtone", t0).as_deref(), Some("content://media/x?a=b"));
+        assert_eq!(s.get_global("zen_mode", t0), None);
+        assert_eq!(files.fallback.runs.get(), 2);
+
+        // without a fallback nothing is spawned, the damaged file just reads as unset
+        let files_only = FileRunner { dir: tmp.path().to_path_buf(), fallback: NoFallback };
+        assert!(files_only.list(Namespace::System).is_err());
+        assert_eq!(files_only.list(Namespace::Secure).unwrap().get(PANORAMIC_AOD).map(String::as_str), Some("1"));
+    }
+}
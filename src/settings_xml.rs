@@ -0,0 +1,261 @@
+// reads the settings provider's own files: /data/system/users/<user>/settings_<namespace>.xml
+// plain XML up to Android 11, Android Binary XML (ABX) since 12, told apart by the magic
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use crate::settings::Namespace;
+
+const ABX_MAGIC: &[u8] = b"ABX\0";
+
+// XmlPullParser event tokens, low nibble of an ABX token byte
+const START_TAG: u8 = 2;
+const END_TAG: u8 = 3;
+const ATTRIBUTE: u8 = 15;
+
+// BinaryXmlSerializer value types, high nibble
+const TYPE_NULL: u8 = 1;
+const TYPE_STRING: u8 = 2;
+const TYPE_STRING_INTERNED: u8 = 3;
+const TYPE_BYTES_HEX: u8 = 4;
+const TYPE_BYTES_BASE64: u8 = 5;
+const TYPE_INT: u8 = 6;
+const TYPE_INT_HEX: u8 = 7;
+const TYPE_LONG: u8 = 8;
+const TYPE_LONG_HEX: u8 = 9;
+const TYPE_FLOAT: u8 = 10;
+const TYPE_DOUBLE: u8 = 11;
+const TYPE_BOOLEAN_TRUE: u8 = 12;
+const TYPE_BOOLEAN_FALSE: u8 = 13;
+
+const INTERNED_NEW: u16 = 0xFFFF; // interned string written inline and added to the table
+
+fn invalid(msg: impl Into<String>) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, msg.into()) }
+
+pub(crate) fn file_name(ns: Namespace) -> String { format!("settings_{}.xml", ns.name()) }
+
+// every set value of a namespace, None-valued settings are left out like `settings list` does
+pub(crate) fn read_namespace(dir: &Path, ns: Namespace) -> io::Result<HashMap<String, String>> {
+    parse(&std::fs::read(dir.join(file_name(ns)))?)
+}
+
+pub(crate) fn is_abx(data: &[u8]) -> bool { data.starts_with(ABX_MAGIC) }
+
+pub(crate) fn parse(data: &[u8]) -> io::Result<HashMap<String, String>> {
+    let settings = if is_abx(data) {
+        parse_abx(&data[ABX_MAGIC.len()..])?
+    } else {
+        parse_xml(std::str::from_utf8(data).map_err(|_| invalid("settings xml is not utf-8"))?)?
+    };
+    Ok(settings.into_iter().filter_map(|(k, v)| Some((k, v?))).collect())
+}
+
+// one <setting> element: a missing value, or the old style "null", means the setting is unset
+// binary values only come as valueBase64 and never hold the flags read here, they read as unset too
+fn setting(attrs: &[(String, Option<String>)]) -> Option<(String, Option<String>)> {
+    let get = |name: &str| attrs.iter().find(|(k, _)| k == name).and_then(|(_, v)| v.clone());
+    let value = get("value").filter(|v| v != "null");
+    Some((get("name")?, value))
+}
+
+// collects the <setting> elements, an element is done once its attributes are
+struct Collector { root: bool, tag: Option<String>, attrs: Vec<(String, Option<String>)>, out: Vec<(String, Option<String>)> }
+
+impl Collector {
+    fn new() -> Self { Self { root: false, tag: None, attrs: Vec::new(), out: Vec::new() } }
+
+    fn start(&mut self, tag: String) {
+        self.finish();
+        self.root |= tag == "settings";
+        self.tag = Some(tag);
+    }
+
+    fn finish(&mut self) {
+        if self.tag.take().as_deref() == Some("setting") && let Some(s) = setting(&self.attrs) { self.out.push(s); }
+        self.attrs.clear();
+    }
+
+    fn done(mut self) -> io::Result<Vec<(String, Option<String>)>> {
+        self.finish();
+        if !self.root { return Err(invalid("no <settings> element")); }
+        Ok(self.out)
+    }
+}
+
+// just enough XML for what FastXmlSerializer writes: elements with quoted attributes, no DTDs
+fn parse_xml(text: &str) -> io::Result<Vec<(String, Option<String>)>> {
+    let mut c = Collector::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        rest = &rest[open + 1..];
+        let skip_to = |rest: &str, end: &str| rest.find(end).map(|i| i + end.len()).ok_or_else(|| invalid(format!("unterminated markup, expected '{}'", end)));
+        if rest.starts_with("!--") {
+            rest = &rest[skip_to(rest, "-->")?..];
+            continue;
+        }
+        if rest.starts_with('?') || rest.starts_with('!') || rest.starts_with('/') {
+            rest = &rest[skip_to(rest, ">")?..];
+            continue;
+        }
+        let name_end = rest.find(|ch: char| ch.is_whitespace() || ch == '/' || ch == '>').ok_or_else(|| invalid("unterminated tag"))?;
+        c.start(rest[..name_end].to_string());
+        rest = &rest[name_end..];
+        loop {
+            rest = rest.trim_start();
+            if let Some(r) = rest.strip_prefix("/>").or_else(|| rest.strip_prefix('>')) { rest = r; break; }
+            let eq = rest.find('=').ok_or_else(|| invalid("attribute without a value"))?;
+            let name = rest[..eq].trim().to_string();
+            rest = rest[eq + 1..].trim_start();
+            let quote = rest.chars().next().filter(|q| *q == '"' || *q == '\'').ok_or_else(|| invalid(format!("unquoted attribute '{}'", name)))?;
+            let end = rest[1..].find(quote).ok_or_else(|| invalid(format!("unterminated attribute '{}'", name)))?;
+            c.attrs.push((name, Some(unescape(&rest[1..end + 1]))));
+            rest = &rest[end + 2..];
+        }
+    }
+    c.done()
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';') else { break; };
+        let decoded = match &rest[1..semi] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            e => e.strip_prefix("#x").map(|h| u32::from_str_radix(h, 16))
+                .or_else(|| e.strip_prefix('#').map(str::parse::<u32>))
+                .and_then(|n| n.ok()).and_then(char::from_u32),
+        };
+        match decoded {
+            Some(ch) => { out.push(ch); rest = &rest[semi + 1..]; },
+            None => { out.push('&'); rest = &rest[1..]; },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// big endian reader over an ABX body
+struct Abx<'d> { data: &'d [u8], pos: usize, interned: Vec<String> }
+
+impl<'d> Abx<'d> {
+    fn take(&mut self, n: usize) -> io::Result<&'d [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + n).ok_or_else(|| invalid(format!("abx truncated at byte {}", self.pos + ABX_MAGIC.len())))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> { Ok(self.take(N)?.try_into().unwrap()) }
+
+    fn u16(&mut self) -> io::Result<u16> { Ok(u16::from_be_bytes(self.array()?)) }
+
+    // length prefixed modified UTF-8
+    fn utf(&mut self) -> io::Result<String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn interned(&mut self) -> io::Result<String> {
+        match self.u16()? {
+            INTERNED_NEW => {
+                let s = self.utf()?;
+                self.interned.push(s.clone());
+                Ok(s)
+            },
+            idx => self.interned.get(idx as usize).cloned().ok_or_else(|| invalid(format!("abx interned string {} not defined", idx))),
+        }
+    }
+
+    // a token's payload as text, None for payloads that aren't kept
+    fn value(&mut self, ty: u8) -> io::Result<Option<String>> {
+        Ok(match ty {
+            TYPE_NULL => None,
+            TYPE_STRING => Some(self.utf()?),
+            TYPE_STRING_INTERNED => Some(self.interned()?),
+            TYPE_BYTES_HEX | TYPE_BYTES_BASE64 => {
+                let len = self.u16()? as usize;
+                self.take(len)?;
+                None
+            },
+            TYPE_INT => Some(i32::from_be_bytes(self.array()?).to_string()),
+            TYPE_INT_HEX => Some(format!("{:x}", i32::from_be_bytes(self.array()?))),
+            TYPE_LONG => Some(i64::from_be_bytes(self.array()?).to_string()),
+            TYPE_LONG_HEX => Some(format!("{:x}", i64::from_be_bytes(self.array()?))),
+            TYPE_FLOAT => Some(f32::from_be_bytes(self.array()?).to_string()),
+            TYPE_DOUBLE => Some(f64::from_be_bytes(self.array()?).to_string()),
+            TYPE_BOOLEAN_TRUE => Some("true".to_string()),
+            TYPE_BOOLEAN_FALSE => Some("false".to_string()),
+            _ => return Err(invalid(format!("abx unknown type {} at byte {}", ty, self.pos + ABX_MAGIC.len() - 1))),
+        })
+    }
+}
+
+// token byte = type << 4 | token, start/end tags carry an interned name, attributes an interned name then a typed value
+fn parse_abx(body: &[u8]) -> io::Result<Vec<(String, Option<String>)>> {
+    let mut r = Abx { data: body, pos: 0, interned: Vec::new() };
+    let mut c = Collector::new();
+    while r.pos < r.data.len() {
+        let [byte] = r.array()?;
+        let (token, ty) = (byte & 0x0f, byte >> 4);
+        match token {
+            START_TAG => {
+                let name = r.interned()?;
+                c.start(name);
+            },
+            END_TAG => { r.interned()?; c.finish(); },
+            ATTRIBUTE => {
+                let name = r.interned()?;
+                let value = r.value(ty)?;
+                c.attrs.push((name, value));
+            },
+            // document markers, text, comments and the like carry nothing we need
+            _ => { r.value(ty)?; c.finish(); },
+        }
+    }
+    c.done()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixtures(format: &str) -> PathBuf { Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/settings").join(format) }
+
+    #[test]
+    fn reads_plain_and_binary_settings_files() {
+        for format in ["xml", "abx"] {
+            let dir = fixtures(format);
+            assert_eq!(is_abx(&std::fs::read(dir.join(file_name(Namespace::Secure))).unwrap()), format == "abx");
+            let secure = read_namespace(&dir, Namespace::Secure).unwrap();
+            assert_eq!(secure.get("panoramic_aod_enable").map(String::as_str), Some("1"), "{}", format);
+            assert_eq!(secure.get("reduce_bright_colors_activated").map(String::as_str), Some("0"), "{}", format);
+            assert_eq!(secure.get("enabled_accessibility_services").map(String::as_str), Some("com.a/.S:com.b/.T&x=\"1\""), "{}", format);
+            // no value attribute means unset
+            assert!(!secure.contains_key("skip_gesture") && !secure.contains_key("sleep_timeout"), "{}", format);
+            assert_eq!(secure.len(), 3, "{}", format);
+
+            let system = read_namespace(&dir, Namespace::System).unwrap();
+            assert_eq!(system.get("screen_brightness_mode").map(String::as_str), Some("1"), "{}", format);
+            assert_eq!(system.get("screen_brightness").map(String::as_str), Some("2047"), "{}", format);
+        }
+        // the abx fixtures come without a global file
+        assert_eq!(read_namespace(&fixtures("abx"), Namespace::Global).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(read_namespace(&fixtures("xml"), Namespace::Global).unwrap().get("low_power").map(String::as_str), Some("0"));
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        let abx = std::fs::read(fixtures("abx").join(file_name(Namespace::Secure))).unwrap();
+        assert!(parse(&abx[..abx.len() / 2]).is_err());
+        assert!(parse(b"ABX\0\x32\x00\x05").is_err()); // interned string that was never defined
+        assert!(parse(b"").is_err());
+        assert!(parse(b"<settings version=\"1\"><setting name=\"a\" value=\"1").is_err());
+        assert_eq!(parse(b"<settings version='1'><setting name='a' value='1'/></settings>").unwrap().get("a").map(String::as_str), Some("1"));
+    }
+}
@@ -1,36 +1,244 @@
-use crate::properties::get_prop;
-use crate::range::BrightnessRange;
-use crate::constants::FALLBACK_MIN;
-
-// brightness property getter
-pub(crate) fn get_prop_brightness(range: &BrightnessRange, is_float: bool) -> i32 {
-    if is_float {
-        if let Some(val_str) = get_prop("debug.tracing.screen_brightness") {
-            if let Ok(f) = val_str.parse::<f32>() {
-                if f == 0.0 { return -1; } // skip
-                let f = f.clamp(0.0, 1.0);
-                return (range.min as f32 + f * (range.max - range.min) as f32).round() as i32;
-            }
-        }
-        FALLBACK_MIN
-    } else {
-        let val = get_prop("debug.tracing.screen_brightness")
-            .and_then(|v| v.split('.').next()?.parse::<i32>().ok());
-        match val {
-            Some(0) => -1, // if val is 0, return -1 to skip write
-            Some(v) => v,
-            None => FALLBACK_MIN
-        }
-    }
-}
-
-// screen state getter
-pub(crate) fn get_screen_state() -> i32 {
-    // screen_state values from debug.tracing.screen_state:
-    // 0: OFF
-    // 1: OFF (AOD)
-    // 2: ON
-    // 3: DOZE (AOD)
-    // 4: DOZE_SUSPEND (AOD DIMMED)
-    get_prop("debug.tracing.screen_state").and_then(|v| v.parse::<i32>().ok()).unwrap_or(2)
-}
+use std::path::{Path, PathBuf};
+use crate::backlight::Backlight;
+use crate::config::Config;
+use crate::logging::{log_d, log_e};
+use crate::properties::{PropertyStore, get_prop};
+
+// android Display.STATE_* values, what debug.tracing.screen_state carries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ScreenState {
+    Unknown,     // 0, nothing decided yet, treated like off
+    Off,         // 1
+    On,          // 2
+    Doze,        // 3, AOD
+    DozeSuspend, // 4, AOD dimmed
+    Vr,          // 5, low persistence VR
+    OnSuspend,   // 6, on with the CPU suspended
+}
+
+impl ScreenState {
+    pub(crate) const ALL: [ScreenState; 7] = [
+        ScreenState::Unknown, ScreenState::Off, ScreenState::On, ScreenState::Doze,
+        ScreenState::DozeSuspend, ScreenState::Vr, ScreenState::OnSuspend,
+    ];
+
+    pub(crate) fn from_raw(v: i32) -> Option<Self> {
+        usize::try_from(v).ok().and_then(|i| Self::ALL.get(i)).copied()
+    }
+
+    pub(crate) fn raw(self) -> i32 { self as i32 }
+
+    // the panel shows content and follows brightness, VR and the CPU suspended included
+    pub(crate) fn is_display_on(self) -> bool {
+        matches!(self, ScreenState::On | ScreenState::Vr | ScreenState::OnSuspend)
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ScreenState::Unknown => "unknown",
+            ScreenState::Off => "off",
+            ScreenState::On => "on",
+            ScreenState::Doze => "doze",
+            ScreenState::DozeSuspend => "doze_suspend",
+            ScreenState::Vr => "vr",
+            ScreenState::OnSuspend => "on_suspend",
+        }
+    }
+
+    // raw value or name
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        s.parse::<i32>().ok().and_then(Self::from_raw).or_else(|| Self::ALL.into_iter().find(|st| st.name() == s))
+    }
+}
+
+// where the screen state can come from, `display.state_sources` lists them by priority
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StateSourceKind {
+    Prop,    // debug.tracing.screen_state, the only one that knows doze
+    Drm,     // DRM connector dpms/status, on or off
+    Fb,      // fbdev blank, on or off, many kernels don't implement reading it
+    BlPower, // backlight class bl_power, on or off
+}
+
+impl StateSourceKind {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "prop" => Some(StateSourceKind::Prop),
+            "drm" => Some(StateSourceKind::Drm),
+            "fb" => Some(StateSourceKind::Fb),
+            "bl_power" => Some(StateSourceKind::BlPower),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            StateSourceKind::Prop => "prop",
+            StateSourceKind::Drm => "drm",
+            StateSourceKind::Fb => "fb",
+            StateSourceKind::BlPower => "bl_power",
+        }
+    }
+}
+
+pub(crate) const STATE_PROP: &str = "debug.tracing.screen_state";
+pub(crate) const DEFAULT_STATE_SOURCES: &str = "prop,drm,fb,bl_power";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Reading { pub(crate) state: ScreenState, pub(crate) source: StateSourceKind }
+
+// FB_BLANK_UNBLANK is on, every other blank level is off
+fn fb_blank_state(path: &Path) -> Option<ScreenState> {
+    let v = std::fs::read_to_string(path).ok()?.trim().parse::<i32>().ok()?;
+    Some(if v == 0 { ScreenState::On } else { ScreenState::Off })
+}
+
+// a disconnected connector is off, otherwise dpms decides
+fn drm_state(dir: &Path) -> Option<ScreenState> {
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).ok().map(|v| v.trim().to_string());
+    if read("status").as_deref() == Some("disconnected") { return Some(ScreenState::Off); }
+    match read("dpms")?.as_str() {
+        "On" => Some(ScreenState::On),
+        "Off" | "Standby" | "Suspend" => Some(ScreenState::Off),
+        _ => None,
+    }
+}
+
+// the panel's DRM connector: card*-<type>-<n>, built-in panel types first
+fn find_drm_connector(sysfs: &Path) -> Option<PathBuf> {
+    let mut connectors: Vec<PathBuf> = std::fs::read_dir(sysfs.join("class/drm")).ok()?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("card") && n.contains('-')))
+        .filter(|p| p.join("dpms").is_file())
+        .collect();
+    connectors.sort();
+    let panel = |p: &PathBuf| p.file_name().and_then(|n| n.to_str()).is_some_and(|n| ["-DSI-", "-eDP-", "-LVDS-"].iter().any(|t| n.contains(t)));
+    let pos = connectors.iter().position(panel).unwrap_or(0);
+    (!connectors.is_empty()).then(|| connectors.swap_remove(pos))
+}
+
+// screen state sources by priority, the first one with a reading wins
+pub(crate) struct StateSources { list: Vec<(StateSourceKind, Option<PathBuf>)> }
+
+impl StateSources {
+    // resolves the sysfs nodes once, sources without one are dropped
+    pub(crate) fn open(cfg: &Config, sysfs: &Path, bl: &Backlight) -> Self {
+        let spec = cfg.state_sources.as_deref().unwrap_or(DEFAULT_STATE_SOURCES);
+        let mut list = Vec::new();
+        for name in spec.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let Some(kind) = StateSourceKind::parse(name) else {
+                log_e(&format!("[State] Unknown screen state source '{}', expected prop, drm, fb or bl_power", name));
+                continue;
+            };
+            let node = match kind {
+                StateSourceKind::Prop => None,
+                StateSourceKind::Drm => find_drm_connector(sysfs),
+                StateSourceKind::Fb => Some(sysfs.join("class/graphics/fb0/blank")).filter(|p| p.is_file()),
+                StateSourceKind::BlPower => Some(bl.dir.join("bl_power")).filter(|p| p.is_file()),
+            };
+            if kind != StateSourceKind::Prop && node.is_none() {
+                if cfg.debug { log_d(&format!("[State] No node for screen state source {}, skipping it", kind.name())); }
+                continue;
+            }
+            if !list.iter().any(|(k, _)| *k == kind) { list.push((kind, node)); }
+        }
+        let sources = Self { list };
+        log_d(&format!("[State] Screen state sources: {}", sources.describe()));
+        sources
+    }
+
+    // "prop, drm (/sys/class/drm/card0-DSI-1)"
+    pub(crate) fn describe(&self) -> String {
+        if self.list.is_empty() { return "none".to_string(); }
+        self.list.iter().map(|(kind, node)| match node {
+            Some(p) => format!("{} ({})", kind.name(), p.display()),
+            None => kind.name().to_string(),
+        }).collect::<Vec<_>>().join(", ")
+    }
+
+    // whether a sysfs source is configured, those can't wake the loop and have to be polled
+    pub(crate) fn has_nodes(&self) -> bool { self.list.iter().any(|(_, node)| node.is_some()) }
+
+    pub(crate) fn read_one(&self, props: &dyn PropertyStore, kind: StateSourceKind) -> Option<ScreenState> {
+        let (_, node) = self.list.iter().find(|(k, _)| *k == kind)?;
+        match (kind, node) {
+            (StateSourceKind::Prop, _) => get_prop(props, STATE_PROP).and_then(|v| v.trim().parse::<i32>().ok()).and_then(ScreenState::from_raw),
+            (StateSourceKind::Drm, Some(dir)) => drm_state(dir),
+            (StateSourceKind::Fb | StateSourceKind::BlPower, Some(path)) => fb_blank_state(path),
+            _ => None,
+        }
+    }
+
+    // None when no source answers, callers keep their last known state instead of guessing
+    pub(crate) fn read(&self, props: &dyn PropertyStore) -> Option<Reading> {
+        self.list.iter().find_map(|(kind, _)| self.read_one(props, *kind).map(|state| Reading { state, source: *kind }))
+    }
+
+    pub(crate) fn kinds(&self) -> impl Iterator<Item = StateSourceKind> + '_ { self.list.iter().map(|(k, _)| *k) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+    use crate::properties::MemoryProps;
+
+    fn sources(spec: &str, sysfs: &Path, bl: &Backlight) -> StateSources {
+        let props = MemoryProps::new();
+        props.set("persist.sys.rianixia.display.state_sources", spec);
+        StateSources::open(&Config::load_files(&props, &[]), sysfs, bl)
+    }
+
+    #[test]
+    fn parses_states() {
+        for st in ScreenState::ALL {
+            assert_eq!(ScreenState::from_raw(st.raw()), Some(st));
+            assert_eq!(ScreenState::parse(st.name()), Some(st));
+        }
+        assert_eq!(ScreenState::parse(" 4"), Some(ScreenState::DozeSuspend));
+        assert_eq!(ScreenState::from_raw(-1), None);
+        assert_eq!(ScreenState::from_raw(7), None);
+        assert_eq!(ScreenState::parse("dozing"), None);
+    }
+
+    #[test]
+    fn sources_in_priority_order_without_assuming_on() {
+        let tmp = TempDir::new();
+        let sys = tmp.path().join("sys");
+        let drm = sys.join("class/drm/card0-DSI-1");
+        let fb = sys.join("class/graphics/fb0");
+        let bl = Backlight::from_dir(sys.join("class/backlight/panel0-backlight"));
+        for dir in [&drm, &fb, &bl.dir, &sys.join("class/drm/card0-DP-1")] { std::fs::create_dir_all(dir).unwrap(); }
+        std::fs::write(sys.join("class/drm/card0-DP-1/dpms"), "On").unwrap();
+        std::fs::write(drm.join("status"), "connected\n").unwrap();
+        std::fs::write(drm.join("dpms"), "Off\n").unwrap();
+        std::fs::write(fb.join("blank"), "").unwrap();
+        std::fs::write(bl.dir.join("bl_power"), "0\n").unwrap();
+        let props = MemoryProps::new();
+        let reading = |s: &StateSources| s.read(&props).map(|r| (r.state, r.source.name()));
+
+        let all = sources("prop, drm, fb, bl_power, bogus", &sys, &bl);
+        assert_eq!(all.kinds().count(), 4);
+        assert!(all.describe().contains("card0-DSI-1"));
+        // no prop, the DSI panel connector beats DP
+        assert_eq!(reading(&all), Some((ScreenState::Off, "drm")));
+        props.set("debug.tracing.screen_state", "3");
+        assert_eq!(reading(&all), Some((ScreenState::Doze, "prop")));
+        props.set("debug.tracing.screen_state", "9");
+        assert_eq!(reading(&all), Some((ScreenState::Off, "drm")));
+
+        // an unreadable fb blank falls through to bl_power
+        let fallback = sources("fb,bl_power", &sys, &bl);
+        assert_eq!(reading(&fallback), Some((ScreenState::On, "bl_power")));
+        std::fs::write(bl.dir.join("bl_power"), "4").unwrap();
+        assert_eq!(reading(&fallback), Some((ScreenState::Off, "bl_power")));
+
+        // missing nodes are dropped, nothing answering is None rather than ON
+        let none = sources("prop", &tmp.path().join("nowhere"), &bl);
+        assert!(!none.has_nodes());
+        props.set("debug.tracing.screen_state", "");
+        assert_eq!(reading(&none), None);
+        assert_eq!(sources("drm", &tmp.path().join("nowhere"), &bl).describe(), "none");
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+use crate::constants::STATUS_PUBLISH_MS;
+use crate::paths::status_prop_prefix;
+use crate::properties::{PropertyStore, set_prop};
+use crate::state::ScreenState;
+
+// what the running loop currently thinks, shared with the control socket and status properties
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Status {
+    pub(crate) mode: &'static str, // default or displaypanel
+    pub(crate) curve: String, // "<mode id>:<curve name>"
+    pub(crate) hw_min: i32,
+    pub(crate) hw_max: i32,
+    pub(crate) input_min: i32,
+    pub(crate) input_max: i32,
+    pub(crate) input: i32, // last framework level
+    pub(crate) last: i32, // last written hw level
+    pub(crate) state: Option<ScreenState>, // None in DisplayPanel mode or before any source answered
+    pub(crate) override_level: Option<i32>,
+    pub(crate) panoramic: bool, // panoramic AOD as last seen
+    pub(crate) errors: u32, // failed brightness writes since start
+}
+
+impl Status {
+    // key/value pairs in a stable order
+    pub(crate) fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("mode", self.mode.to_string()),
+            ("curve", self.curve.clone()),
+            ("hw_min", self.hw_min.to_string()),
+            ("hw_max", self.hw_max.to_string()),
+            ("input_min", self.input_min.to_string()),
+            ("input_max", self.input_max.to_string()),
+            ("input", self.input.to_string()),
+            ("last", self.last.to_string()),
+            ("state", self.state.map_or(-1, ScreenState::raw).to_string()),
+            ("override", self.override_level.map_or("none".to_string(), |v| v.to_string())),
+            ("panoramic", self.panoramic.to_string()),
+            ("errors", self.errors.to_string()),
+        ]
+    }
+}
+
+// mirrors the status into sys.rianixia.display.<key>, only changed keys and at most once per STATUS_PUBLISH_MS
+// so a ramp doesn't turn into a property storm
+pub(crate) struct StatusProps { shown: Vec<(&'static str, String)>, last: Option<Instant> }
+
+impl StatusProps {
+    pub(crate) fn new() -> Self { Self { shown: Vec::new(), last: None } }
+
+    // returns how long until held back changes may go out, None if everything is published
+    pub(crate) fn publish(&mut self, props: &dyn PropertyStore, status: &Status, now: Instant) -> Option<Duration> {
+        let fields = status.fields();
+        let changed: Vec<&(&'static str, String)> = fields.iter().filter(|f| !self.shown.contains(f)).collect();
+        if changed.is_empty() { return None; }
+        let interval = Duration::from_millis(STATUS_PUBLISH_MS);
+        if let Some(last) = self.last && now.duration_since(last) < interval {
+            return Some(interval - now.duration_since(last));
+        }
+        for (key, val) in changed {
+            set_prop(props, &format!("{}{}", status_prop_prefix(), key), val);
+        }
+        self.shown = fields;
+        self.last = Some(now);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::MemoryProps;
+
+    #[test]
+    fn publishes_changed_keys_rate_limited() {
+        let props = MemoryProps::new();
+        let mut published = StatusProps::new();
+        let t0 = Instant::now();
+        let mut status = Status { mode: "default", last: 100, ..Default::default() };
+        assert_eq!(published.publish(&props, &status, t0), None);
+        assert_eq!(props.get("sys.rianixia.display.last").as_deref(), Some("100"));
+        assert_eq!(props.get("sys.rianixia.display.override").as_deref(), Some("none"));
+
+        // nothing changed, nothing set
+        let serial = props.change_serial();
+        assert_eq!(published.publish(&props, &status, t0 + Duration::from_millis(10)), None);
+        assert_eq!(props.change_serial(), serial);
+
+        // held back inside the interval, then only the changed key goes out
+        status.last = 200;
+        assert_eq!(published.publish(&props, &status, t0 + Duration::from_millis(400)), Some(Duration::from_millis(STATUS_PUBLISH_MS - 400)));
+        assert_eq!(props.get("sys.rianixia.display.last").as_deref(), Some("100"));
+        assert_eq!(published.publish(&props, &status, t0 + Duration::from_millis(STATUS_PUBLISH_MS)), None);
+        assert_eq!(props.get("sys.rianixia.display.last").as_deref(), Some("200"));
+        assert_eq!(props.change_serial(), serial.map(|s| s + 1));
+    }
+}
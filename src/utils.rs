@@ -1,109 +1,136 @@
-use std::process::Command;
-use crate::logging::{log_d, log_e};
-use crate::properties::{get_prop_int, set_prop};
-use crate::paths::{persist_custom_devmax_prop, persist_custom_devmin_prop, max_bright_path, min_bright_path, persist_hw_min, persist_hw_max};
-
-// panoramic aod check
-pub(crate) fn is_panoramic_aod_enabled(dbg: bool) -> bool {
-    let output = Command::new("settings")
-                             .arg("get")
-                             .arg("secure")
-                             .arg("panoramic_aod_enable")
-                             .output();
-
-    match output {
-        Ok(out) => {
-            if out.status.success() {
-                let result = String::from_utf8_lossy(&out.stdout).trim().to_string();
-                if dbg { log_d(&format!("[DisplayAdaptor] panoramic_aod_enable result: '{}'", result)); }
-                result == "1"
-            } else {
-                if dbg { log_e(&format!("[DisplayAdaptor] 'settings get' command failed: {}", String::from_utf8_lossy(&out.stderr))); }
-                false
-            }
-        },
-        Err(e) => {
-            if dbg { log_e(&format!("[DisplayAdaptor] Failed to execute 'settings' command: {}", e)); }
-            false
-        }
-    }
-}
-
-// file & property readers
-pub(crate) fn read_file_int(path: &str) -> Option<i32> {
-    if let Ok(content) = std::fs::read_to_string(path) {
-        let numeric_part: String = content
-            .trim()
-            .chars()
-            .take_while(|c| c.is_digit(10))
-            .collect();
-        numeric_part.parse().ok()
-    } else {
-        None
-    }
-}
-
-// hardware brightness getter
-pub(crate) fn get_max_brightness(dbg: bool) -> i32 {
-    if let Some(custom_max) = get_prop_int(persist_custom_devmax_prop()) {
-        if custom_max > 0 {
-            if dbg { log_d(&format!("[DisplayAdaptor] Using custom devmax brightness: {}", custom_max)); }
-            return custom_max;
-        }
-    }
-    
-    if let Some(cached_max) = get_prop_int(persist_hw_max()) {
-        if dbg { log_d(&format!("[DisplayAdaptor] Using cached hw_max: {}", cached_max)); }
-        return cached_max;
-    }
-
-    match read_file_int(max_bright_path()) {
-        Some(val) => {
-            if dbg { log_d(&format!("[DisplayAdaptor] Detected hw_max: {}. Saving to prop.", val)); }
-            set_prop(persist_hw_max(), &val.to_string());
-            val
-        },
-        None => {
-            if dbg { log_d("[DisplayAdaptor] Failed to detect hw_max, using default 511"); }
-            511
-        }
-    }
-}
-
-pub(crate) fn get_min_brightness(dbg: bool) -> i32 {
-    // Check for custom devmin override first
-    if let Some(custom_min) = get_prop_int(persist_custom_devmin_prop()) {
-        if custom_min > 0 {
-            if dbg { log_d(&format!("[DisplayAdaptor] Using custom devmin brightness for calculation: {}", custom_min)); }
-            return custom_min;
-        }
-    }
-
-    if let Some(cached_min) = get_prop_int(persist_hw_min()) {
-        if cached_min > 0 {
-            if dbg { log_d(&format!("[DisplayAdaptor] Using cached hw_min: {}", cached_min)); }
-            return cached_min;
-        } else {
-             // Force correction if cached value is invalid (e.g. 0)
-             if dbg { log_d(&format!("[DisplayAdaptor] Cached hw_min invalid ({}), forcing to 1.", cached_min)); }
-             set_prop(persist_hw_min(), "1");
-             return 1;
-        }
-    }
-
-    match read_file_int(min_bright_path()) {
-        Some(mut val) => {
-            if val <= 0 {
-                if dbg { log_d("[DisplayAdaptor] Detected hw_min <= 0 (screen off?), falling back to 1."); }
-                val = 1;
-            }
-            if dbg { log_d(&format!("[DisplayAdaptor] Saving hw_min: {} to prop.", val)); }
-            set_prop(persist_hw_min(), &val.to_string());
-            val
-        },
-        None => {
-            if dbg { log_d("[DisplayAdaptor] Failed to detect hw_min, using default 1"); }
-            1
-        }
-    }
-}
\ No newline at end of file
+use std::path::Path;
+use crate::backlight::Backlight;
+use crate::config::Config;
+use crate::logging::log_d;
+use crate::properties::{PropertyStore, get_prop_int, set_prop};
+use crate::paths::{persist_hw_min, persist_hw_max};
+
+// file & property readers
+pub(crate) fn read_file_int<P: AsRef<Path>>(path: P) -> Option<i32> {
+    if let Ok(content) = std::fs::read_to_string(path) {
+        let numeric_part: String = content
+            .trim()
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        numeric_part.parse().ok()
+    } else {
+        None
+    }
+}
+
+// hardware brightness getters: config override, then the node, then the value an earlier run read from it
+// the node always wins so a different device or kernel doesn't inherit a stale range
+pub(crate) fn get_max_brightness(props: &dyn PropertyStore, cfg: &Config, bl: &Backlight, dbg: bool) -> i32 {
+    max_brightness(props, cfg, bl, true, dbg)
+}
+
+pub(crate) fn get_min_brightness(props: &dyn PropertyStore, cfg: &Config, bl: &Backlight, dbg: bool) -> i32 {
+    min_brightness(props, cfg, bl, true, dbg)
+}
+
+// the same lookups without updating the cache, for read-only diagnostics
+pub(crate) fn read_max_brightness(props: &dyn PropertyStore, cfg: &Config, bl: &Backlight, dbg: bool) -> i32 {
+    max_brightness(props, cfg, bl, false, dbg)
+}
+
+pub(crate) fn read_min_brightness(props: &dyn PropertyStore, cfg: &Config, bl: &Backlight, dbg: bool) -> i32 {
+    min_brightness(props, cfg, bl, false, dbg)
+}
+
+fn max_brightness(props: &dyn PropertyStore, cfg: &Config, bl: &Backlight, cache: bool, dbg: bool) -> i32 {
+    if let Some(custom_max) = cfg.hw_max {
+        if dbg { log_d(&format!("[DisplayAdaptor] Using custom devmax brightness: {}", custom_max)); }
+        return custom_max;
+    }
+
+    let cached_max = get_prop_int(props, persist_hw_max());
+    match read_file_int(bl.max_path()) {
+        Some(val) => {
+            if dbg { log_d(&format!("[DisplayAdaptor] Detected hw_max: {} from {}", val, bl.max_path().display())); }
+            if cache && cached_max != Some(val) { set_prop(props, persist_hw_max(), &val.to_string()); }
+            val
+        },
+        None => match cached_max {
+            Some(val) => {
+                if dbg { log_d(&format!("[DisplayAdaptor] Can't read {}, using cached hw_max: {}", bl.max_path().display(), val)); }
+                val
+            },
+            None => {
+                if dbg { log_d("[DisplayAdaptor] Failed to detect hw_max, using default 511"); }
+                511
+            },
+        },
+    }
+}
+
+fn min_brightness(props: &dyn PropertyStore, cfg: &Config, bl: &Backlight, cache: bool, dbg: bool) -> i32 {
+    // Check for custom devmin override first
+    if let Some(custom_min) = cfg.hw_min {
+        if dbg { log_d(&format!("[DisplayAdaptor] Using custom devmin brightness for calculation: {}", custom_min)); }
+        return custom_min;
+    }
+
+    let cached_min = get_prop_int(props, persist_hw_min());
+    match read_file_int(bl.min_path()) {
+        Some(mut val) => {
+            if val <= 0 {
+                if dbg { log_d("[DisplayAdaptor] Detected hw_min <= 0 (screen off?), falling back to 1."); }
+                val = 1;
+            }
+            if dbg { log_d(&format!("[DisplayAdaptor] Detected hw_min: {} from {}", val, bl.min_path().display())); }
+            if cache && cached_min != Some(val) { set_prop(props, persist_hw_min(), &val.to_string()); }
+            val
+        },
+        // an invalid cached value (e.g. 0) reads as 1
+        None => match cached_min {
+            Some(val) => {
+                if dbg { log_d(&format!("[DisplayAdaptor] Can't read {}, using cached hw_min: {}", bl.min_path().display(), val)); }
+                val.max(1)
+            },
+            None => {
+                if dbg { log_d("[DisplayAdaptor] Failed to detect hw_min, using default 1"); }
+                1
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::harness::TempDir;
+    use crate::properties::MemoryProps;
+
+    #[test]
+    fn node_range_beats_the_cached_one() {
+        let tmp = TempDir::new();
+        let bl = Backlight::from_dir(tmp.path().to_path_buf());
+        let props = MemoryProps::with(&[(persist_hw_max(), "4095"), (persist_hw_min(), "0")]);
+        let cfg = Config::load_files(&props, &[]);
+        // nothing to read, the cache from an earlier run
+        assert_eq!((get_min_brightness(&props, &cfg, &bl, false), get_max_brightness(&props, &cfg, &bl, false)), (1, 4095));
+
+        // a different panel: its own range, remembered for next time
+        std::fs::write(tmp.path().join("max_brightness"), "2047").unwrap();
+        std::fs::write(tmp.path().join("min_brightness"), "2").unwrap();
+        assert_eq!((get_min_brightness(&props, &cfg, &bl, false), get_max_brightness(&props, &cfg, &bl, false)), (2, 2047));
+        assert_eq!((props.get(persist_hw_min()).as_deref(), props.get(persist_hw_max()).as_deref()), (Some("2"), Some("2047")));
+
+        let missing = Backlight::from_dir(PathBuf::from("/nonexistent"));
+        assert_eq!(get_max_brightness(&MemoryProps::new(), &cfg, &missing, false), 511);
+    }
+
+    #[test]
+    fn read_lookups_leave_the_cache_alone() {
+        let tmp = TempDir::new();
+        let bl = Backlight::from_dir(tmp.path().to_path_buf());
+        std::fs::write(tmp.path().join("max_brightness"), "2047").unwrap();
+        std::fs::write(tmp.path().join("min_brightness"), "2").unwrap();
+        let props = MemoryProps::with(&[(persist_hw_max(), "4095")]);
+        let cfg = Config::load_files(&props, &[]);
+        assert_eq!((read_min_brightness(&props, &cfg, &bl, false), read_max_brightness(&props, &cfg, &bl, false)), (2, 2047));
+        assert_eq!((props.get(persist_hw_min()), props.get(persist_hw_max()).as_deref()), (None, Some("4095")));
+    }
+}
@@ -0,0 +1,191 @@
+use std::ffi::{CString, OsString};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use crate::constants::PROP_WAIT_ON_MS;
+use crate::properties::PropertyStore;
+
+const WATCH_MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MODIFY | libc::IN_CREATE | libc::IN_MOVED_TO;
+const EVENT_HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+// eventfd used to wake a blocked loop from another thread
+pub(crate) struct WakeFd(OwnedFd);
+impl WakeFd {
+    pub(crate) fn new() -> io::Result<Self> {
+        let raw = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if raw < 0 { return Err(io::Error::last_os_error()); }
+        Ok(Self(unsafe { OwnedFd::from_raw_fd(raw) }))
+    }
+
+    pub(crate) fn raw(&self) -> RawFd { self.0.as_raw_fd() }
+
+    pub(crate) fn notify(&self) {
+        let one = 1u64;
+        unsafe { libc::write(self.0.as_raw_fd(), &one as *const u64 as *const _, 8) };
+    }
+}
+
+// fires an eventfd from a helper thread whenever one of `keys` changes value
+// lets a loop blocked on a file also see a few properties, the thread ends within a wait after drop
+pub(crate) struct PropWake { wake: Arc<WakeFd>, stop: Arc<AtomicBool> }
+impl PropWake {
+    pub(crate) fn spawn(props: Arc<dyn PropertyStore + Send + Sync>, keys: Vec<&'static str>) -> io::Result<Self> {
+        let wake = Arc::new(WakeFd::new()?);
+        let stop = Arc::new(AtomicBool::new(false));
+        let (w, st) = (wake.clone(), stop.clone());
+        let mut last: Vec<Option<String>> = keys.iter().map(|k| props.get(k)).collect();
+        std::thread::Builder::new().name("prop-wake".into()).spawn(move || {
+            while !st.load(Ordering::Relaxed) {
+                // snapshot before comparing so a change in between still ends the wait
+                let serial = props.change_serial();
+                let cur: Vec<Option<String>> = keys.iter().map(|k| props.get(k)).collect();
+                if cur != last { last = cur; w.notify(); }
+                props.wait_change(serial, &keys, Duration::from_millis(PROP_WAIT_ON_MS));
+            }
+        })?;
+        Ok(Self { wake, stop })
+    }
+
+    pub(crate) fn raw(&self) -> RawFd { self.wake.raw() }
+}
+impl Drop for PropWake {
+    fn drop(&mut self) { self.stop.store(true, Ordering::Relaxed); }
+}
+
+// reset an eventfd counter, the fd is non-blocking
+fn drain_eventfd(fd: RawFd) {
+    let mut count = 0u64;
+    unsafe { libc::read(fd, &mut count as *mut u64 as *mut _, 8) };
+}
+
+// inotify watcher for a single file
+// watches the parent dir so the file being deleted and re-created is still seen
+// writes to any of the `wakes` eventfds also end the wait
+pub(crate) struct FileWatcher { fd: OwnedFd, name: OsString, wakes: Vec<RawFd> }
+impl FileWatcher {
+    pub(crate) fn new(path: &Path, wakes: &[RawFd]) -> io::Result<Self> {
+        let name = path.file_name().ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?.to_os_string();
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let raw = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if raw < 0 { return Err(io::Error::last_os_error()); }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        let c_dir = CString::new(dir.as_os_str().as_bytes()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_dir.as_ptr(), WATCH_MASK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd, name, wakes: wakes.to_vec() })
+    }
+
+    // block until the file changes, a wake fd fires or the timeout passes (None waits forever)
+    // Ok(true) if either happened
+    pub(crate) fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let timeout_ms = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        let mut pfds: Vec<libc::pollfd> = std::iter::once(self.fd.as_raw_fd()).chain(self.wakes.iter().copied())
+            .map(|fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+            .collect();
+        let res = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, timeout_ms) };
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            return if err.kind() == io::ErrorKind::Interrupted { Ok(false) } else { Err(err) };
+        }
+        if res == 0 { return Ok(false); }
+        let mut woken = false;
+        for p in pfds[1..].iter().filter(|p| p.revents & libc::POLLIN != 0) {
+            drain_eventfd(p.fd);
+            woken = true;
+        }
+        let changed = pfds[0].revents & libc::POLLIN != 0 && self.drain()?;
+        Ok(changed || woken)
+    }
+
+    // read all pending events, true if any of them is for our file
+    fn drain(&self) -> io::Result<bool> {
+        let mut buf = [0u8; 4096];
+        let mut hit = false;
+        loop {
+            let n = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len()) };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock { return Ok(hit); }
+                return Err(err);
+            }
+            let n = n as usize;
+            let mut off = 0;
+            while off + EVENT_HEADER <= n {
+                let ev = unsafe { std::ptr::read_unaligned(buf.as_ptr().add(off) as *const libc::inotify_event) };
+                let name_bytes = &buf[off + EVENT_HEADER..off + EVENT_HEADER + ev.len as usize];
+                let name_len = name_bytes.iter().position(|b| *b == 0).unwrap_or(name_bytes.len());
+                if OsString::from_vec(name_bytes[..name_len].to_vec()) == self.name { hit = true; }
+                off += EVENT_HEADER + ev.len as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+    use crate::properties::MemoryProps;
+
+    const SHORT: Option<Duration> = Some(Duration::from_millis(50));
+
+    #[test]
+    fn wakes_on_writes_and_recreation() {
+        let tmp = TempDir::new();
+        let file = tmp.path().join("oplus_brightness");
+        std::fs::write(&file, "100").unwrap();
+        let w = FileWatcher::new(&file, &[]).unwrap();
+        assert!(!w.wait(SHORT).unwrap());
+
+        std::fs::write(&file, "200").unwrap();
+        assert!(w.wait(SHORT).unwrap());
+        assert!(!w.wait(SHORT).unwrap());
+
+        // other files in the dir don't count
+        std::fs::write(tmp.path().join("other"), "1").unwrap();
+        assert!(!w.wait(SHORT).unwrap());
+
+        // deleted and created again, still seen through the dir watch
+        std::fs::remove_file(&file).unwrap();
+        assert!(!w.wait(SHORT).unwrap());
+        std::fs::write(&file, "300").unwrap();
+        assert!(w.wait(SHORT).unwrap());
+    }
+
+    #[test]
+    fn wake_fd_ends_the_wait() {
+        let tmp = TempDir::new();
+        let wake = WakeFd::new().unwrap();
+        let w = FileWatcher::new(&tmp.path().join("oplus_brightness"), &[wake.raw()]).unwrap();
+        wake.notify();
+        assert!(w.wait(None).unwrap());
+        // drained, the next wait times out
+        assert!(!w.wait(SHORT).unwrap());
+
+        std::thread::scope(|s| {
+            s.spawn(|| { std::thread::sleep(Duration::from_millis(20)); wake.notify(); });
+            assert!(w.wait(Some(Duration::from_secs(5))).unwrap());
+        });
+    }
+
+    #[test]
+    fn prop_wake_fires_on_watched_props_only() {
+        let props = Arc::new(MemoryProps::new());
+        let tmp = TempDir::new();
+        let pw = PropWake::spawn(props.clone(), vec!["sys.lease"]).unwrap();
+        let w = FileWatcher::new(&tmp.path().join("oplus_brightness"), &[pw.raw()]).unwrap();
+        props.set("sys.other", "1");
+        assert!(!w.wait(SHORT).unwrap());
+        props.set("sys.lease", "qr:hw:2047:1000");
+        assert!(w.wait(Some(Duration::from_secs(5))).unwrap());
+    }
+}
@@ -1,22 +1,407 @@
-use std::ffi::CString;
-use std::io;
-use crate::logging::{log_d, log_e};
-
-// brightness write function
-pub(crate) fn write_brightness(fd: i32, val: i32, last_val: &mut i32, dbg: bool) {
-    if *last_val == val {
-        return;
-    }
-    if dbg { log_d(&format!("[DisplayAdaptor] Writing brightness: {} -> {}", *last_val, val)); }
-
-    let s = val.to_string();
-    let c_str = match CString::new(s.as_bytes()) { Ok(c) => c, Err(_) => { log_e("[DisplayAdaptor] Failed to create CString"); return; } };
-    let bytes = c_str.as_bytes_with_nul();
-
-    let result = unsafe { libc::write(fd, bytes.as_ptr() as *const _, bytes.len()) };
-    if result < 0 {
-        if dbg { log_e(&format!("[DisplayAdaptor] Write failed for value {}: {}", val, io::Error::last_os_error())); }
-    } else {
-        *last_val = val;
-    }
-}
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::backlight::Backlight;
+use crate::config::Config;
+use crate::constants::{REOPEN_AFTER_FAILURES, REOPEN_BACKOFF_MAX_MS, REOPEN_BACKOFF_MIN_MS};
+use crate::logging::{log_d, log_e};
+use crate::utils::read_file_int;
+
+// how a level is spelled for the node, drivers parse it differently
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Encoding { Decimal, DecimalNewline, DecimalNul, Hex }
+impl Encoding {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "decimal" | "dec" => Some(Encoding::Decimal),
+            "newline" | "decimal-newline" => Some(Encoding::DecimalNewline),
+            "nul" | "decimal-nul" => Some(Encoding::DecimalNul),
+            "hex" => Some(Encoding::Hex),
+            _ => None,
+        }
+    }
+}
+
+// resolution change for drivers that expect a different range than the detected one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Scale { Identity, Mul(i32), Div(i32), Shl(u32), Shr(u32) }
+impl Scale {
+    // "*N", "/N", "<<N" or ">>N"
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let scale = if let Some(n) = s.strip_prefix("<<") {
+            Scale::Shl(n.trim().parse().ok().filter(|n| *n < 31)?)
+        } else if let Some(n) = s.strip_prefix(">>") {
+            Scale::Shr(n.trim().parse().ok().filter(|n| *n < 31)?)
+        } else if let Some(n) = s.strip_prefix('*') {
+            Scale::Mul(n.trim().parse().ok().filter(|n| *n > 0)?)
+        } else if let Some(n) = s.strip_prefix('/') {
+            Scale::Div(n.trim().parse().ok().filter(|n| *n > 0)?)
+        } else {
+            return None;
+        };
+        Some(scale)
+    }
+
+    pub(crate) fn apply(self, val: i32) -> i32 {
+        match self {
+            Scale::Identity => val,
+            Scale::Mul(n) => val.saturating_mul(n),
+            Scale::Div(n) => val / n,
+            Scale::Shl(n) => val.checked_shl(n).filter(|v| v >> n == val).unwrap_or(i32::MAX),
+            Scale::Shr(n) => val >> n,
+        }
+    }
+
+    // a level read back from the node in hw units, lossy for / and >>
+    pub(crate) fn unapply(self, raw: i32) -> i32 {
+        match self {
+            Scale::Identity => raw,
+            Scale::Mul(n) => raw / n,
+            Scale::Div(n) => Scale::Mul(n).apply(raw),
+            Scale::Shl(n) => raw >> n,
+            Scale::Shr(n) => Scale::Shl(n).apply(raw),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct WriteFormat { pub(crate) encoding: Encoding, pub(crate) scale: Scale }
+impl WriteFormat {
+    // decimal with a trailing NUL, what the adaptor always wrote
+    pub(crate) const DEFAULT: Self = Self { encoding: Encoding::DecimalNul, scale: Scale::Identity };
+
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        let encoding = match cfg.write_format.as_deref() {
+            None => Self::DEFAULT.encoding,
+            Some(s) => Encoding::parse(s).unwrap_or_else(|| {
+                log_e(&format!("[Writer] Unknown write format '{}', using nul", s));
+                Self::DEFAULT.encoding
+            }),
+        };
+        let scale = match cfg.write_scale.as_deref() {
+            None => Scale::Identity,
+            Some(s) => Scale::parse(s).unwrap_or_else(|| {
+                log_e(&format!("[Writer] Invalid write scale '{}', expected *N, /N, <<N or >>N", s));
+                Scale::Identity
+            }),
+        };
+        Self { encoding, scale }
+    }
+
+    // the bytes written for a hw level
+    pub(crate) fn encode(&self, val: i32) -> Vec<u8> {
+        let val = self.scale.apply(val);
+        let mut out = match self.encoding {
+            Encoding::Hex => format!("0x{:x}", val),
+            _ => val.to_string(),
+        }.into_bytes();
+        match self.encoding {
+            Encoding::DecimalNewline => out.push(b'\n'),
+            Encoding::DecimalNul => out.push(0),
+            Encoding::Decimal | Encoding::Hex => {},
+        }
+        out
+    }
+}
+
+// one raw write of `val`
+// always writes at offset 0: sysfs ignores it and regular files then hold only the latest value
+fn write_raw(fd: i32, val: i32, fmt: &WriteFormat) -> io::Result<()> {
+    let bytes = fmt.encode(val);
+    let result = unsafe { libc::pwrite(fd, bytes.as_ptr() as *const _, bytes.len(), 0) };
+    if result < 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+}
+
+// brightness write function, Ok(true) if a new value was written
+pub(crate) fn write_brightness(fd: i32, val: i32, fmt: &WriteFormat, last_val: &mut i32, dbg: bool) -> io::Result<bool> {
+    if *last_val == val {
+        return Ok(false);
+    }
+    if dbg { log_d(&format!("[DisplayAdaptor] Writing brightness: {} -> {}", *last_val, val)); }
+
+    match write_raw(fd, val, fmt) {
+        Ok(()) => {
+            *last_val = val;
+            Ok(true)
+        },
+        Err(e) => {
+            if dbg { log_e(&format!("[DisplayAdaptor] Write failed for value {}: {}", val, e)); }
+            Err(e)
+        },
+    }
+}
+
+// errors that mean the node behind the fd is gone, not that the value was refused
+fn node_gone(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::EBADF | libc::ENODEV | libc::ENXIO | libc::ENOENT))
+}
+
+// owns the brightness node: retries the open with backoff, reopens it once writes keep failing
+// (panel driver reset, SELinux relabel) and resends the last level asked for
+pub(crate) struct BacklightWriter {
+    path: PathBuf,
+    file: Option<File>,
+    format: WriteFormat,
+    readback: Option<Readback>,
+    last_val: i32, // last level that reached the node
+    intended: Option<i32>, // last level asked for
+    failures: u32, // failed writes in a row
+    backoff: Duration,
+    retry_at: Option<Instant>, // next attempt after a failure
+}
+
+impl BacklightWriter {
+    pub(crate) fn open(path: PathBuf, format: WriteFormat, readback: Option<Readback>, now: Instant) -> Self {
+        let mut w = Self {
+            path, file: None, format, readback, last_val: -1, intended: None, failures: 0,
+            backoff: Duration::from_millis(REOPEN_BACKOFF_MIN_MS), retry_at: None,
+        };
+        w.reopen(now);
+        w
+    }
+
+    fn reopen(&mut self, now: Instant) -> bool {
+        match OpenOptions::new().write(true).open(&self.path) {
+            Ok(f) => {
+                log_d(&format!("[Writer] Opened {}", self.path.display()));
+                self.file = Some(f);
+                self.last_val = -1; // whatever the node holds now, resend
+                true
+            },
+            Err(e) => {
+                log_e(&format!("[Writer] Could not open {}, retrying in {}ms: {}", self.path.display(), self.backoff.as_millis(), e));
+                self.file = None;
+                self.back_off(now);
+                false
+            },
+        }
+    }
+
+    fn back_off(&mut self, now: Instant) {
+        self.retry_at = Some(now + self.backoff);
+        self.backoff = (self.backoff * 2).min(Duration::from_millis(REOPEN_BACKOFF_MAX_MS));
+    }
+
+    // formats and readback follow config reloads, the node stays
+    pub(crate) fn reconfigure(&mut self, format: WriteFormat, readback: Option<Readback>) {
+        if format != self.format { self.last_val = -1; } // rewrite in the new format
+        self.format = format;
+        self.readback = readback;
+    }
+
+    // last level that reached the node, -1 before the first write
+    pub(crate) fn last(&self) -> i32 { self.last_val }
+
+    pub(crate) fn format(&self) -> WriteFormat { self.format }
+
+    // level asked for that hasn't reached the node yet
+    pub(crate) fn pending(&self) -> Option<i32> { self.intended.filter(|v| *v != self.last_val) }
+
+    // how long until a pending level should be tried again
+    pub(crate) fn retry_in(&self, now: Instant) -> Option<Duration> {
+        self.pending()?;
+        Some(self.retry_at.map_or(Duration::ZERO, |t| t.saturating_duration_since(now)))
+    }
+
+    // Ok(true) if `val` reached the node, Ok(false) if it was already there or the node is still closed
+    // `sleep` is only used by the readback
+    pub(crate) fn write(&mut self, val: i32, now: Instant, sleep: &dyn Fn(Duration), dbg: bool) -> io::Result<bool> {
+        self.intended = Some(val);
+        if self.file.is_none() && (self.retry_at.is_some_and(|t| now < t) || !self.reopen(now)) {
+            return Ok(false);
+        }
+        let Some(file) = &self.file else { return Ok(false); };
+        let prev = self.last_val;
+        match write_brightness(file.as_raw_fd(), val, &self.format, &mut self.last_val, dbg) {
+            Ok(written) => {
+                self.failures = 0;
+                self.backoff = Duration::from_millis(REOPEN_BACKOFF_MIN_MS);
+                self.retry_at = None;
+                if written && let Some(rb) = &mut self.readback { rb.verify(file.as_raw_fd(), val, self.format.scale.apply(prev), &self.format, sleep, dbg); }
+                Ok(written)
+            },
+            Err(e) => {
+                self.last_val = prev;
+                self.failures += 1;
+                if node_gone(&e) || self.failures >= REOPEN_AFTER_FAILURES {
+                    log_e(&format!("[Writer] {} stopped taking writes ({}), reopening in {}ms", self.path.display(), e, self.backoff.as_millis()));
+                    self.file = None;
+                }
+                self.back_off(now);
+                Err(e)
+            },
+        }
+    }
+}
+
+// what the panel did with a write, as seen on the readback node
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Readout { Match, Clamped(i32), Ignored, Unreadable }
+
+// reads the node back after a write: panels sometimes clamp levels or drop writes while busy
+pub(crate) struct Readback { path: PathBuf, retries: u32, delay: Duration, clamped: u32, ignored: u32 }
+
+impl Readback {
+    // "brightness" and "actual_brightness" live in the backlight dir, anything else is an absolute path
+    pub(crate) fn from_config(cfg: &Config, bl: &Backlight) -> Option<Self> {
+        let node = cfg.readback.as_deref()?;
+        let path = if node.starts_with('/') { PathBuf::from(node) } else { bl.dir.join(node) };
+        log_d(&format!("[Writer] Verifying writes against {}", path.display()));
+        Some(Self { path, retries: cfg.readback_retries, delay: Duration::from_millis(cfg.readback_delay_ms), clamped: 0, ignored: 0 })
+    }
+
+    pub(crate) fn path(&self) -> &Path { &self.path }
+
+    // check a write of `val` over the node's previous `prev`, rewriting up to `retries` times while it doesn't stick
+    // the node reports decimal levels in its own resolution, `prev` and the result are in node units
+    // every read waits `delay` through `sleep` first, some drivers apply levels asynchronously
+    pub(crate) fn verify(&mut self, fd: i32, val: i32, prev: i32, fmt: &WriteFormat, sleep: &dyn Fn(Duration), dbg: bool) -> Readout {
+        let expect = fmt.scale.apply(val);
+        let mut attempt = 0;
+        let read = loop {
+            if !self.delay.is_zero() { sleep(self.delay); }
+            let read = read_file_int(&self.path);
+            if read == Some(expect) { return Readout::Match; }
+            if attempt == self.retries { break read; }
+            attempt += 1;
+            if dbg { log_d(&format!("[Writer] Readback {:?} after writing {}, retry {}/{}", read, val, attempt, self.retries)); }
+            if let Err(e) = write_raw(fd, val, fmt) { log_e(&format!("[Writer] Rewrite of {} failed: {}", val, e)); }
+        };
+        let out = match read {
+            None => Readout::Unreadable,
+            Some(r) if r == prev => { self.ignored += 1; Readout::Ignored },
+            Some(r) => { self.clamped += 1; Readout::Clamped(r) },
+        };
+        if dbg {
+            log_d(&format!("[Writer] Wrote {}, panel reports {:?} ({} clamped, {} ignored so far)", val, out, self.clamped, self.ignored));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::os::unix::io::AsRawFd;
+    use crate::harness::TempDir;
+
+    #[test]
+    fn formats_write_expected_bytes() {
+        let tmp = TempDir::new();
+        let node = tmp.path().join("brightness");
+        let cases: [(&str, Option<&str>, &[u8]); 7] = [
+            ("decimal", None, b"1023"),
+            ("newline", None, b"1023\n"),
+            ("nul", None, b"1023\0"),
+            ("hex", None, b"0x3ff"),
+            ("decimal", Some("*4"), b"4092"),
+            ("newline", Some(">>2"), b"255\n"),
+            ("hex", Some("<<4"), b"0x3ff0"),
+        ];
+        for (encoding, scale, expected) in cases {
+            let fmt = WriteFormat {
+                encoding: Encoding::parse(encoding).unwrap(),
+                scale: scale.map_or(Scale::Identity, |s| Scale::parse(s).unwrap()),
+            };
+            std::fs::write(&node, "").unwrap();
+            let file = OpenOptions::new().write(true).open(&node).unwrap();
+            let mut last_val = -1;
+            assert!(write_brightness(file.as_raw_fd(), 1023, &fmt, &mut last_val, false).unwrap());
+            assert_eq!(std::fs::read(&node).unwrap(), expected, "{} {:?}", encoding, scale);
+        }
+        for bad in ["", "4", "*0", "/-1", "<<40", "x2"] {
+            assert_eq!(Scale::parse(bad), None, "{}", bad);
+        }
+        assert_eq!(Scale::Shl(4).apply(i32::MAX / 2), i32::MAX);
+        for (scale, raw, hw) in [("*4", 4092, 1023), ("/4", 255, 1020), ("<<4", 0x3ff0, 1023), (">>2", 255, 1020)] {
+            assert_eq!(Scale::parse(scale).unwrap().unapply(raw), hw, "{}", scale);
+        }
+        assert_eq!(Encoding::parse("octal"), None);
+    }
+
+    #[test]
+    fn readback_reports_clamped_and_ignored_writes() {
+        let tmp = TempDir::new();
+        let node = tmp.path().join("brightness");
+        let actual = tmp.path().join("actual_brightness");
+        std::fs::write(&node, "0").unwrap();
+        let file = OpenOptions::new().write(true).open(&node).unwrap();
+        let fd = file.as_raw_fd();
+        let mut last_val = -1;
+        assert!(write_brightness(fd, 1000, &WriteFormat::DEFAULT, &mut last_val, false).unwrap());
+        assert_eq!(std::fs::read(&node).unwrap(), b"1000\0");
+
+        let mut rb = Readback { path: node, retries: 2, delay: Duration::ZERO, clamped: 0, ignored: 0 };
+        assert_eq!(rb.verify(fd, 1000, 0, &WriteFormat::DEFAULT, &|_| {}, false), Readout::Match);
+
+        let mut rb = Readback { path: actual.clone(), retries: 2, delay: Duration::ZERO, clamped: 0, ignored: 0 };
+        assert_eq!(rb.verify(fd, 1000, 0, &WriteFormat::DEFAULT, &|_| {}, false), Readout::Unreadable);
+        std::fs::write(&actual, "800\n").unwrap();
+        assert_eq!(rb.verify(fd, 1000, 0, &WriteFormat::DEFAULT, &|_| {}, false), Readout::Clamped(800));
+        assert_eq!(rb.verify(fd, 1200, 800, &WriteFormat::DEFAULT, &|_| {}, false), Readout::Ignored);
+        assert_eq!((rb.clamped, rb.ignored), (1, 1));
+    }
+
+    #[test]
+    fn readback_waits_for_drivers_applying_levels_later() {
+        let tmp = TempDir::new();
+        let node = tmp.path().join("brightness");
+        let actual = tmp.path().join("actual_brightness");
+        std::fs::write(&node, "").unwrap();
+        std::fs::write(&actual, "800").unwrap();
+        let file = OpenOptions::new().write(true).open(&node).unwrap();
+        let mut rb = Readback { path: actual.clone(), retries: 2, delay: Duration::from_millis(10), clamped: 0, ignored: 0 };
+
+        // the driver catches up during the second wait
+        let slept = std::cell::Cell::new(Duration::ZERO);
+        let sleep = |d: Duration| {
+            slept.set(slept.get() + d);
+            if slept.get() >= Duration::from_millis(20) { std::fs::write(&actual, "1000").unwrap(); }
+        };
+        assert_eq!(rb.verify(file.as_raw_fd(), 1000, 800, &WriteFormat::DEFAULT, &sleep, false), Readout::Match);
+        assert_eq!(slept.get(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn writer_reopens_a_replaced_node_and_resends() {
+        let tmp = TempDir::new();
+        let node = tmp.path().join("brightness");
+        let t0 = Instant::now();
+        let ms = |v: u64| t0 + Duration::from_millis(v);
+
+        // node missing at start: the first level waits for it
+        let mut w = BacklightWriter::open(node.clone(), WriteFormat::DEFAULT, None, t0);
+        assert!(!w.write(100, t0, &|_| {}, false).unwrap());
+        assert_eq!((w.pending(), w.retry_in(t0)), (Some(100), Some(Duration::from_millis(REOPEN_BACKOFF_MIN_MS))));
+        std::fs::write(&node, "").unwrap();
+        assert!(w.write(100, ms(REOPEN_BACKOFF_MIN_MS), &|_| {}, false).unwrap());
+        assert_eq!(std::fs::read(&node).unwrap(), b"100\0");
+        assert_eq!((w.last(), w.pending()), (100, None));
+
+        // driver reset: the old fd fails with EBADF, the recreated node gets the level again
+        std::fs::remove_file(&node).unwrap();
+        let dead = File::open("/dev/null").unwrap();
+        assert!(unsafe { libc::dup2(dead.as_raw_fd(), w.file.as_ref().unwrap().as_raw_fd()) } >= 0);
+        assert!(w.write(200, ms(200), &|_| {}, false).is_err());
+        assert_eq!(w.pending(), Some(200));
+        std::fs::write(&node, "0").unwrap();
+        assert!(!w.write(200, ms(250), &|_| {}, false).unwrap());
+        assert!(w.write(200, ms(200 + REOPEN_BACKOFF_MIN_MS), &|_| {}, false).unwrap());
+        assert_eq!(std::fs::read(&node).unwrap(), b"200\0");
+        assert_eq!(w.retry_in(ms(300)), None);
+
+        // a node that keeps refusing writes is reopened after REOPEN_AFTER_FAILURES
+        let full = tmp.path().join("full");
+        std::os::unix::fs::symlink("/dev/full", &full).unwrap();
+        let mut w = BacklightWriter::open(full.clone(), WriteFormat::DEFAULT, None, t0);
+        for i in 0..REOPEN_AFTER_FAILURES {
+            assert!(w.file.is_some());
+            assert_eq!(w.write(300, ms(i as u64 * 1000), &|_| {}, false).unwrap_err().raw_os_error(), Some(libc::ENOSPC));
+        }
+        assert!(w.file.is_none());
+    }
+}